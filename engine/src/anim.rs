@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use crate::sprite::GPUSprite;
+
+// how a clip behaves once it reaches its last frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayMode {
+    Loop,
+    PingPong,
+    Once,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AnimEvent {
+    // a Loop or PingPong clip wrapped around to its first frame
+    Looped(String),
+    // a Once clip reached its last frame and stopped there
+    Finished(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Clip {
+    // each frame is a sheet_region: [x, y, w, h] in normalized sheet coordinates
+    pub frames: Vec<[f32; 4]>,
+    // seconds each frame stays on screen
+    pub frame_time: f32,
+    pub mode: PlayMode,
+}
+
+impl Clip {
+    pub fn new(frames: Vec<[f32; 4]>, frame_time: f32, mode: PlayMode) -> Self {
+        Self { frames, frame_time, mode }
+    }
+
+    // build a clip out of `count` equally sized frames laid out left to right in the atlas,
+    // starting at `first` = [x, y, w, h] in pixels like the TexturePacker json in content/info.txt
+    pub fn from_strip(
+        first: [f32; 4],
+        count: usize,
+        sheet_size: [f32; 2],
+        frame_time: f32,
+        mode: PlayMode,
+    ) -> Self {
+        let [x, y, w, h] = first;
+        let frames = (0..count)
+            .map(|i| {
+                [
+                    (x + i as f32 * w) / sheet_size[0],
+                    y / sheet_size[1],
+                    w / sheet_size[0],
+                    h / sheet_size[1],
+                ]
+            })
+            .collect();
+        Self::new(frames, frame_time, mode)
+    }
+
+    pub fn duration(&self) -> f32 {
+        match self.mode {
+            PlayMode::PingPong if self.frames.len() > 1 => {
+                (2 * self.frames.len() - 2) as f32 * self.frame_time
+            }
+            _ => self.frames.len() as f32 * self.frame_time,
+        }
+    }
+
+    // which frame to show `t` seconds into one pass of the clip
+    fn frame_at(&self, t: f32) -> usize {
+        let len = self.frames.len();
+        if len <= 1 || self.frame_time <= 0.0 {
+            return 0;
+        }
+        let step = (t / self.frame_time) as usize;
+        match self.mode {
+            PlayMode::Once => step.min(len - 1),
+            PlayMode::Loop => step % len,
+            PlayMode::PingPong => {
+                let period = 2 * len - 2;
+                let step = step % period;
+                if step < len {
+                    step
+                } else {
+                    period - step
+                }
+            }
+        }
+    }
+}
+
+// plays named clips on one sprite by rewriting its sheet_region
+#[derive(Default)]
+pub struct Animator {
+    clips: HashMap<String, Clip>,
+    current: Option<String>,
+    elapsed: f32,
+    finished: bool,
+}
+
+impl Animator {
+    pub fn add_clip(&mut self, name: &str, clip: Clip) {
+        self.clips.insert(name.to_string(), clip);
+    }
+
+    // start a clip from its first frame; playing the clip that's already running does nothing
+    pub fn play(&mut self, name: &str) {
+        if self.current.as_deref() == Some(name) {
+            return;
+        }
+        self.restart(name);
+    }
+
+    pub fn restart(&mut self, name: &str) {
+        if !self.clips.contains_key(name) {
+            log::warn!("No animation clip named {:?}", name);
+            return;
+        }
+        self.current = Some(name.to_string());
+        self.elapsed = 0.0;
+        self.finished = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // which frame of the current clip is showing, e.g. to flag frames for effects
+    pub fn frame(&self) -> Option<usize> {
+        let clip = &self.clips[self.current.as_ref()?];
+        Some(clip.frame_at(self.elapsed))
+    }
+
+    // advance by `dt` seconds and write the current frame into `sprite`
+    pub fn update(&mut self, dt: f32, sprite: &mut GPUSprite) -> Option<AnimEvent> {
+        let name = self.current.as_ref()?;
+        let clip = &self.clips[name];
+        if clip.frames.is_empty() {
+            return None;
+        }
+        let mut event = None;
+        if !self.finished {
+            self.elapsed += dt;
+            let duration = clip.duration();
+            if self.elapsed >= duration && duration > 0.0 {
+                match clip.mode {
+                    PlayMode::Once => {
+                        self.elapsed = duration;
+                        self.finished = true;
+                        event = Some(AnimEvent::Finished(name.clone()));
+                    }
+                    _ => {
+                        self.elapsed %= duration;
+                        event = Some(AnimEvent::Looped(name.clone()));
+                    }
+                }
+            }
+        }
+        sprite.sheet_region = clip.frames[clip.frame_at(self.elapsed)];
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // frame i is [i, 0, 1, 1], so sheet_region[0] says which frame is showing
+    fn clip(count: usize, mode: PlayMode) -> Clip {
+        Clip::new((0..count).map(|i| [i as f32, 0.0, 1.0, 1.0]).collect(), 0.25, mode)
+    }
+
+    fn frame(animator: &mut Animator, dt: f32) -> (usize, Option<AnimEvent>) {
+        let mut sprite: GPUSprite = bytemuck::Zeroable::zeroed();
+        let event = animator.update(dt, &mut sprite);
+        (sprite.sheet_region[0] as usize, event)
+    }
+
+    #[test]
+    fn frames_change_at_frame_boundaries() {
+        let clip = clip(4, PlayMode::Loop);
+        assert_eq!(clip.frame_at(0.0), 0);
+        assert_eq!(clip.frame_at(0.24), 0);
+        assert_eq!(clip.frame_at(0.25), 1);
+        assert_eq!(clip.frame_at(0.75), 3);
+        assert_eq!(clip.frame_at(1.0), 0);
+    }
+
+    #[test]
+    fn ping_pong_plays_back_without_repeating_ends() {
+        let clip = clip(3, PlayMode::PingPong);
+        assert_eq!(clip.duration(), 1.0);
+        let frames: Vec<usize> = (0..5).map(|i| clip.frame_at(i as f32 * 0.25)).collect();
+        assert_eq!(frames, [0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn loop_wraps_and_reports_it() {
+        let mut animator = Animator::default();
+        animator.add_clip("walk", clip(2, PlayMode::Loop));
+        animator.play("walk");
+        assert_eq!(frame(&mut animator, 0.3), (1, None));
+        assert_eq!(frame(&mut animator, 0.3), (0, Some(AnimEvent::Looped("walk".into()))));
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn once_stops_on_its_last_frame() {
+        let mut animator = Animator::default();
+        animator.add_clip("die", clip(3, PlayMode::Once));
+        animator.play("die");
+        assert_eq!(frame(&mut animator, 0.5), (2, None));
+        assert_eq!(frame(&mut animator, 0.25), (2, Some(AnimEvent::Finished("die".into()))));
+        assert!(animator.is_finished());
+        // no more events, and it stays put
+        assert_eq!(frame(&mut animator, 10.0), (2, None));
+        animator.restart("die");
+        assert_eq!(frame(&mut animator, 0.0), (0, None));
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn playing_the_running_clip_keeps_its_place() {
+        let mut animator = Animator::default();
+        animator.add_clip("walk", clip(4, PlayMode::Loop));
+        animator.play("walk");
+        frame(&mut animator, 0.5);
+        animator.play("walk");
+        assert_eq!(frame(&mut animator, 0.0).0, 2);
+        assert_eq!(animator.frame(), Some(2));
+        animator.play("missing");
+        assert_eq!(animator.current(), Some("walk"));
+        animator.stop();
+        assert_eq!(animator.frame(), None);
+    }
+}
@@ -0,0 +1,501 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use image::RgbaImage;
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use crate::atlas::{self, Atlas, AtlasOptions};
+use crate::error::{Error, Result};
+use crate::gpu::WGPU;
+use crate::texture::{TextureId, TextureOptions, TextureRegistry};
+use crate::vfs;
+
+// A typed reference to an asset in an Assets manager, usable as soon as the load is
+// requested; the asset itself is there once Assets::update has received it.
+pub struct Handle<T> {
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+// derives would want T: Clone etc.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+// a load that failed: the file asked for, and the engine error that stopped it
+#[derive(Debug)]
+pub struct AssetError {
+    pub path: PathBuf,
+    // boxed since Error can hold an AssetError
+    pub error: Box<Error>,
+}
+
+// the engine errors already name the file
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+// what a request turns into once the asset is in place
+#[derive(Clone, Copy)]
+enum Target {
+    Texture(TextureId, TextureOptions),
+    Sound(usize),
+    Text(usize),
+    Atlas(usize, AtlasOptions),
+}
+
+// the file-reading and decoding half of a load, which doesn't need the GPU
+enum Decoded {
+    Image(RgbaImage),
+    // boxed, it's much bigger than the others
+    Sound(Box<StaticSoundData>),
+    Text(String),
+    Images(Vec<(String, RgbaImage)>),
+}
+
+// a decoded asset after any GPU work, ready for its slot
+enum Loaded {
+    Texture,
+    Sound(Box<StaticSoundData>),
+    Text(String),
+    Atlas(Atlas),
+}
+
+enum Slot<T> {
+    Loading,
+    Loaded(T),
+    Failed,
+}
+
+impl<T> Slot<T> {
+    fn get(&self) -> Option<&T> {
+        match self {
+            Slot::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+}
+
+struct Request {
+    path: PathBuf,
+    target: Target,
+    // a reload of something already asked for, which progress() leaves out
+    reload: bool,
+}
+
+// Loads textures, sounds, atlases and text files (levels, JSON) by path in the background.
+// Each load_* call hands back a handle straight away and has the file read and decoded
+// on another thread (or fetched from the server on the web); update() puts finished
+// assets in place once a frame. Asking for the same path twice gives the same handle.
+// Nothing panics on a missing or broken file: the asset just never arrives and errors()
+// says why.
+pub struct Assets {
+    requests: Vec<Request>,
+    sender: Sender<(usize, Result<Decoded>)>,
+    receiver: Receiver<(usize, Result<Decoded>)>,
+    // first loads asked for and finished, for progress()
+    loads: usize,
+    finished: usize,
+    errors: Vec<AssetError>,
+    // reloads that arrived since take_reloaded()
+    reloaded: Vec<PathBuf>,
+    sounds: Vec<Slot<StaticSoundData>>,
+    sound_paths: HashMap<PathBuf, usize>,
+    texts: Vec<Slot<String>>,
+    text_paths: HashMap<PathBuf, usize>,
+    atlases: Vec<Slot<Atlas>>,
+    atlas_paths: HashMap<(PathBuf, AtlasOptions), usize>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            requests: Vec::new(),
+            sender,
+            receiver,
+            loads: 0,
+            finished: 0,
+            errors: Vec::new(),
+            reloaded: Vec::new(),
+            sounds: Vec::new(),
+            sound_paths: HashMap::new(),
+            texts: Vec::new(),
+            text_paths: HashMap::new(),
+            atlases: Vec::new(),
+            atlas_paths: HashMap::new(),
+        }
+    }
+}
+
+fn decode(path: &Path, target: &Target) -> Result<Decoded> {
+    match target {
+        Target::Atlas(..) => atlas::read_dir_images(path).map(Decoded::Images),
+        _ => decode_bytes(path, vfs::read(path).map_err(Error::io(path))?, target),
+    }
+}
+
+fn decode_bytes(path: &Path, bytes: Cow<'static, [u8]>, target: &Target) -> Result<Decoded> {
+    match target {
+        Target::Texture(..) => crate::gpu::decode_image(path, &bytes).map(Decoded::Image),
+        Target::Sound(_) => StaticSoundData::from_cursor(Cursor::new(bytes), StaticSoundSettings::default())
+            .map(|sound| Decoded::Sound(Box::new(sound)))
+            .map_err(|e| Error::Audio(format!("{}: {}", path.display(), e))),
+        Target::Text(_) => String::from_utf8(bytes.into_owned())
+            .map(Decoded::Text)
+            .map_err(|e| Error::io(path)(io::Error::new(io::ErrorKind::InvalidData, e))),
+        Target::Atlas(..) => Err(Error::Atlas(format!("{} is a directory of images", path.display()))),
+    }
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn request(&mut self, path: &Path, target: Target, reload: bool) {
+        let index = self.requests.len();
+        let sender = self.sender.clone();
+        let owned = path.to_path_buf();
+        // the receiver only goes away with the Assets, when nobody's waiting
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || {
+            let _ = sender.send((index, decode(&owned, &target)));
+        });
+        // no threads on the web, but fetches don't block, and decoding happens between frames
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = match target {
+                Target::Atlas(..) => decode(&owned, &target),
+                _ => match vfs::fetch(&owned).await {
+                    Ok(bytes) => decode_bytes(&owned, bytes, &target),
+                    Err(e) => Err(Error::io(&owned)(e)),
+                },
+            };
+            let _ = sender.send((index, result));
+        });
+        if !reload {
+            self.loads += 1;
+        }
+        self.requests.push(Request {
+            path: path.to_path_buf(),
+            target,
+            reload,
+        });
+    }
+
+    // The texture's id is valid right away and draws as nothing until the image arrives,
+    // so sprites can be set up while it loads. Registered with `textures` under `path`,
+    // so TextureRegistry::load_with and reload find it too.
+    pub fn load_texture(
+        &mut self,
+        gpu: &WGPU,
+        textures: &mut TextureRegistry,
+        path: &Path,
+        options: TextureOptions,
+    ) -> TextureId {
+        let (id, new) = textures.reserve(gpu, path, options);
+        if new {
+            self.request(path, Target::Texture(id, options), false);
+        }
+        id
+    }
+
+    pub fn load_sound(&mut self, path: &Path) -> Handle<StaticSoundData> {
+        if let Some(&index) = self.sound_paths.get(path) {
+            return Handle::new(index);
+        }
+        let index = self.sounds.len();
+        self.sounds.push(Slot::Loading);
+        self.sound_paths.insert(path.to_path_buf(), index);
+        self.request(path, Target::Sound(index), false);
+        Handle::new(index)
+    }
+
+    // a UTF-8 file the game parses itself, e.g. a level layout or atlas JSON
+    pub fn load_text(&mut self, path: &Path) -> Handle<String> {
+        if let Some(&index) = self.text_paths.get(path) {
+            return Handle::new(index);
+        }
+        let index = self.texts.len();
+        self.texts.push(Slot::Loading);
+        self.text_paths.insert(path.to_path_buf(), index);
+        self.request(path, Target::Text(index), false);
+        Handle::new(index)
+    }
+
+    // every .png directly inside `dir`, packed like Atlas::load_dir
+    pub fn load_atlas(&mut self, dir: &Path, options: AtlasOptions) -> Handle<Atlas> {
+        let key = (dir.to_path_buf(), options);
+        if let Some(&index) = self.atlas_paths.get(&key) {
+            return Handle::new(index);
+        }
+        let index = self.atlases.len();
+        self.atlases.push(Slot::Loading);
+        self.atlas_paths.insert(key, index);
+        self.request(dir, Target::Atlas(index, options), false);
+        Handle::new(index)
+    }
+
+    // Load whatever came from `path` again, e.g. when AssetWatcher sees it change: a sound
+    // or text file, or an atlas whose directory holds the image. Handles keep the old asset
+    // until the new one arrives, and for good if the reload fails; take_reloaded() says
+    // when it's in. Reloads don't count towards progress(). Textures reload through
+    // TextureRegistry.
+    pub fn reload(&mut self, path: &Path) {
+        if let Some(&index) = self.sound_paths.get(path) {
+            self.request(path, Target::Sound(index), true);
+        }
+        if let Some(&index) = self.text_paths.get(path) {
+            self.request(path, Target::Text(index), true);
+        }
+        let is_png = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if !is_png {
+            return;
+        }
+        let mut atlases: Vec<(usize, PathBuf, AtlasOptions)> = self
+            .atlas_paths
+            .iter()
+            .filter(|((dir, _), _)| path.parent() == Some(dir.as_path()))
+            .map(|((dir, options), &index)| (index, dir.clone(), *options))
+            .collect();
+        // the same order every time, whatever the map's
+        atlases.sort_by_key(|&(index, ..)| index);
+        for (index, dir, options) in atlases {
+            self.request(&dir, Target::Atlas(index, options), true);
+        }
+    }
+
+    // The paths (atlas directories for atlases) whose reloads have arrived since the last
+    // call. Text the game parsed, or regions looked up in a repacked atlas, want redoing.
+    pub fn take_reloaded(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.reloaded)
+    }
+
+    // put whatever finished loading in place; call once a frame
+    pub fn update(&mut self, gpu: &WGPU, textures: &mut TextureRegistry) {
+        let done: Vec<(usize, Result<Decoded>)> = self.receiver.try_iter().collect();
+        for (index, result) in done {
+            let Request { ref path, target, .. } = self.requests[index];
+            let loaded = result.and_then(|decoded| match (target, decoded) {
+                (Target::Texture(id, options), Decoded::Image(img)) => {
+                    let texture = gpu.texture_from_image(&img, path.to_str(), &options);
+                    textures.replace(gpu, id, texture);
+                    Ok(Loaded::Texture)
+                }
+                // a rebuild takes over the old pages, so sprites drawing from them keep working
+                (Target::Atlas(i, options), Decoded::Images(images)) => {
+                    let old_pages = self.atlases[i].get().map(Atlas::page_ids).unwrap_or_default();
+                    Atlas::build(gpu, textures, images, options, &old_pages).map(Loaded::Atlas)
+                }
+                (_, Decoded::Sound(sound)) => Ok(Loaded::Sound(sound)),
+                (_, Decoded::Text(text)) => Ok(Loaded::Text(text)),
+                // decode() always answers a request with its own kind
+                _ => Err(Error::io(path)(io::Error::new(io::ErrorKind::InvalidData, "wrong kind of asset"))),
+            });
+            self.finish(index, loaded);
+        }
+    }
+
+    // Count a finished request and put what it loaded in its slot. A failed first load
+    // marks the slot failed; a failed reload keeps what loaded before.
+    fn finish(&mut self, index: usize, result: Result<Loaded>) {
+        let Request { ref path, target, reload } = self.requests[index];
+        if !reload {
+            self.finished += 1;
+        } else if result.is_ok() {
+            self.reloaded.push(path.clone());
+        }
+        match (target, result) {
+            (Target::Sound(i), Ok(Loaded::Sound(sound))) => self.sounds[i] = Slot::Loaded(*sound),
+            (Target::Text(i), Ok(Loaded::Text(text))) => self.texts[i] = Slot::Loaded(text),
+            (Target::Atlas(i, _), Ok(Loaded::Atlas(atlas))) => self.atlases[i] = Slot::Loaded(atlas),
+            (_, Ok(_)) => {}
+            (target, Err(error)) => {
+                if !reload {
+                    match target {
+                        Target::Sound(i) => self.sounds[i] = Slot::Failed,
+                        Target::Text(i) => self.texts[i] = Slot::Failed,
+                        Target::Atlas(i, _) => self.atlases[i] = Slot::Failed,
+                        Target::Texture(..) => {}
+                    }
+                }
+                let error = AssetError {
+                    path: path.clone(),
+                    error: Box::new(error),
+                };
+                log::warn!("Couldn't load {}", error);
+                self.errors.push(error);
+            }
+        }
+    }
+
+    pub fn sound(&self, handle: Handle<StaticSoundData>) -> Option<&StaticSoundData> {
+        self.sounds[handle.index].get()
+    }
+
+    pub fn text(&self, handle: Handle<String>) -> Option<&str> {
+        self.texts[handle.index].get().map(String::as_str)
+    }
+
+    pub fn atlas(&self, handle: Handle<Atlas>) -> Option<&Atlas> {
+        self.atlases[handle.index].get()
+    }
+
+    // how much of what's been asked for has arrived (or failed), 0 to 1; reloads aren't
+    // counted, so a hot reload doesn't bring the loading screen back
+    pub fn progress(&self) -> f32 {
+        if self.loads == 0 {
+            1.0
+        } else {
+            self.finished as f32 / self.loads as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished == self.loads
+    }
+
+    // every load that failed so far
+    pub fn errors(&self) -> &[AssetError] {
+        &self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_found(path: &str) -> Error {
+        Error::io(Path::new(path))(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    // the requests also go to worker threads, whose answers these tests never receive;
+    // finish() stands in for update() with whatever result each test wants
+    #[test]
+    fn same_path_gives_same_handle() {
+        let mut assets = Assets::new();
+        let level = assets.load_text(Path::new("content/level1.txt"));
+        assert_eq!(assets.load_text(Path::new("content/level1.txt")), level);
+        assert_ne!(assets.load_text(Path::new("content/level2.txt")), level);
+        let hit = assets.load_sound(Path::new("content/player_hit.ogg"));
+        assert_eq!(assets.load_sound(Path::new("content/player_hit.ogg")), hit);
+        let options = AtlasOptions::default();
+        let atlas = assets.load_atlas(Path::new("content/sprites"), options);
+        assert_eq!(assets.load_atlas(Path::new("content/sprites"), options), atlas);
+        let padded = AtlasOptions { padding: 4, ..options };
+        assert_ne!(assets.load_atlas(Path::new("content/sprites"), padded), atlas);
+        // only the new paths were requested
+        assert_eq!(assets.requests.len(), 5);
+    }
+
+    #[test]
+    fn progress_counts_loads_and_failures() {
+        let mut assets = Assets::new();
+        assert!(assets.is_done());
+        assert_eq!(assets.progress(), 1.0);
+        let a = assets.load_text(Path::new("a.txt"));
+        let b = assets.load_text(Path::new("b.txt"));
+        assert!(!assets.is_done());
+        assert_eq!(assets.progress(), 0.0);
+        assets.finish(0, Ok(Loaded::Text("level a".to_string())));
+        assert_eq!(assets.progress(), 0.5);
+        assert_eq!(assets.text(a), Some("level a"));
+        assets.finish(1, Err(not_found("b.txt")));
+        assert!(assets.is_done());
+        assert_eq!(assets.text(b), None);
+        assert!(matches!(assets.texts[b.index], Slot::Failed));
+        assert_eq!(assets.errors().len(), 1);
+        assert_eq!(assets.errors()[0].path, Path::new("b.txt"));
+        assert!(matches!(*assets.errors()[0].error, Error::Io { .. }));
+    }
+
+    #[test]
+    fn reloads_keep_the_old_asset_and_leave_progress_alone() {
+        let mut assets = Assets::new();
+        let level = assets.load_text(Path::new("level.txt"));
+        assets.finish(0, Ok(Loaded::Text("v1".to_string())));
+        assets.reload(Path::new("level.txt"));
+        assert!(assets.is_done());
+        assert_eq!(assets.progress(), 1.0);
+        // a failed reload is reported but the last good version stays
+        assets.finish(1, Err(not_found("level.txt")));
+        assert_eq!(assets.text(level), Some("v1"));
+        assert_eq!(assets.errors().len(), 1);
+        assets.reload(Path::new("level.txt"));
+        assert!(assets.take_reloaded().is_empty());
+        assets.finish(2, Ok(Loaded::Text("v2".to_string())));
+        assert_eq!(assets.text(level), Some("v2"));
+        assert!(assets.is_done());
+        assert_eq!(assets.take_reloaded(), vec![PathBuf::from("level.txt")]);
+        assert!(assets.take_reloaded().is_empty());
+        // paths nothing was loaded from are ignored
+        assets.reload(Path::new("other.txt"));
+        assert_eq!(assets.requests.len(), 3);
+    }
+
+    #[test]
+    fn changed_images_reload_their_atlas() {
+        let mut assets = Assets::new();
+        let options = AtlasOptions::default();
+        assets.load_atlas(Path::new("content/sprites"), options);
+        assets.load_atlas(Path::new("content/ui"), options);
+        assets.reload(Path::new("content/sprites/player.png"));
+        assert_eq!(assets.requests.len(), 3);
+        assert_eq!(assets.requests[2].path, Path::new("content/sprites"));
+        assert!(assets.requests[2].reload);
+        // not an image, or not directly inside an atlas directory
+        assets.reload(Path::new("content/sprites/notes.txt"));
+        assets.reload(Path::new("content/sprites/old/player.png"));
+        assets.reload(Path::new("content/player.png"));
+        assert_eq!(assets.requests.len(), 3);
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use image::RgbaImage;
+use crate::error::{Error, Result};
+use crate::gpu::{decode_image, WGPU};
+use crate::texture::{TextureId, TextureOptions, TextureRegistry};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct AtlasOptions {
+    // largest page on each side, in pixels; images that don't fit go on another page
+    pub max_size: u32,
+    // empty pixels left between neighbouring images
+    pub padding: u32,
+    // how many times each image's edge pixels are repeated outward, so linear
+    // filtering at the edge of a region samples the image and not its neighbour
+    pub extrude: u32,
+    // how the pages are uploaded and sampled
+    pub texture: TextureOptions,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_size: 2048,
+            padding: 2,
+            extrude: 1,
+            texture: TextureOptions::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasRegion {
+    // the page this image ended up on
+    pub texture: TextureId,
+    // [x, y, w, h] in page pixels, y down
+    pub rect: [u32; 4],
+    // rect as fractions of the page, ready to use as a GPUSprite sheet_region
+    pub sheet_region: [f32; 4],
+}
+
+// Images packed into one or more atlas pages at load time, looked up by name
+// (the file name without its extension when loaded from a directory).
+pub struct Atlas {
+    regions: HashMap<String, AtlasRegion>,
+    pages: Vec<TextureId>,
+    // ids of pages a reload no longer needed, released and kept for the next one that grows
+    spare: Vec<TextureId>,
+}
+
+// an image's page and [x, y, w, h] on that page
+type Placement = (usize, [u32; 4]);
+
+// Skyline bottom-left packing: the top edge of everything placed so far is kept as
+// [x, y, w] segments sorted by x, and each new rect sits as low as it can on them.
+struct Skyline {
+    size: u32,
+    segments: Vec<[u32; 3]>,
+    // how much of the page is covered, so it can be cropped
+    used: [u32; 2],
+}
+
+impl Skyline {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            segments: vec![[0, 0, size]],
+            used: [0, 0],
+        }
+    }
+
+    // the lowest (then leftmost) spot for a w x h rect, as (segment index, x, y)
+    fn find(&self, w: u32, h: u32) -> Option<(usize, u32, u32)> {
+        let mut best: Option<(usize, u32, u32)> = None;
+        for (i, &[x, _, _]) in self.segments.iter().enumerate() {
+            if x + w > self.size {
+                break;
+            }
+            let y = self.segments[i..]
+                .iter()
+                .take_while(|s| s[0] < x + w)
+                .map(|s| s[1])
+                .max()
+                .unwrap_or(0);
+            if y + h > self.size {
+                continue;
+            }
+            if best.is_none_or(|(_, bx, by)| (y, x) < (by, bx)) {
+                best = Some((i, x, y));
+            }
+        }
+        best
+    }
+
+    fn place(&mut self, index: usize, x: u32, y: u32, w: u32, h: u32) {
+        self.segments.insert(index, [x, y + h, w]);
+        // cut away whatever the new segment now covers
+        let right = x + w;
+        let i = index + 1;
+        while i < self.segments.len() && self.segments[i][0] < right {
+            let end = self.segments[i][0] + self.segments[i][2];
+            if end <= right {
+                self.segments.remove(i);
+            } else {
+                self.segments[i] = [right, self.segments[i][1], end - right];
+                break;
+            }
+        }
+        // join neighbours at the same height
+        let mut i = 0;
+        while i + 1 < self.segments.len() {
+            if self.segments[i][1] == self.segments[i + 1][1] {
+                self.segments[i][2] += self.segments[i + 1][2];
+                self.segments.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+        self.used = [self.used[0].max(x + w), self.used[1].max(y + h)];
+    }
+}
+
+// copy `img` to (x, y) on `page`, repeating its border `extrude` pixels outward
+fn blit_extruded(page: &mut RgbaImage, img: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (w, h) = img.dimensions();
+    let e = extrude as i64;
+    for dy in -e..h as i64 + e {
+        for dx in -e..w as i64 + e {
+            let src = img.get_pixel(dx.clamp(0, w as i64 - 1) as u32, dy.clamp(0, h as i64 - 1) as u32);
+            page.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *src);
+        }
+    }
+}
+
+// Pack images onto as few pages as possible. Returns the page images and where each
+// input image went, in order.
+fn pack(images: &[(String, RgbaImage)], options: AtlasOptions) -> Result<(Vec<RgbaImage>, Vec<Placement>)> {
+    let border = options.extrude * 2 + options.padding;
+    // tallest first keeps the skyline flat
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&i| {
+        let (w, h) = images[i].1.dimensions();
+        (std::cmp::Reverse(h), std::cmp::Reverse(w))
+    });
+
+    let mut skylines: Vec<Skyline> = Vec::new();
+    let mut placed = vec![(0, [0; 4]); images.len()];
+    for i in order {
+        let (name, img) = &images[i];
+        let (w, h) = img.dimensions();
+        let (slot_w, slot_h) = (w + border, h + border);
+        if slot_w > options.max_size || slot_h > options.max_size {
+            return Err(Error::Atlas(format!(
+                "{} is {}x{}, too big for a {}px atlas page",
+                name, w, h, options.max_size
+            )));
+        }
+        let spot = skylines
+            .iter()
+            .enumerate()
+            .find_map(|(page, sky)| sky.find(slot_w, slot_h).map(|spot| (page, spot)));
+        let (page, (index, x, y)) = match spot {
+            Some(spot) => spot,
+            None => {
+                skylines.push(Skyline::new(options.max_size));
+                let page = skylines.len() - 1;
+                // an empty page always has room for a slot no bigger than the page
+                (page, skylines[page].find(slot_w, slot_h).unwrap_or((0, 0, 0)))
+            }
+        };
+        skylines[page].place(index, x, y, slot_w, slot_h);
+        placed[i] = (page, [x + options.extrude, y + options.extrude, w, h]);
+    }
+
+    let mut pages: Vec<RgbaImage> = skylines
+        .iter()
+        .map(|sky| RgbaImage::new(sky.used[0].max(1), sky.used[1].max(1)))
+        .collect();
+    for ((_, img), &(page, [x, y, _, _])) in images.iter().zip(&placed) {
+        blit_extruded(&mut pages[page], img, x, y, options.extrude);
+    }
+    Ok((pages, placed))
+}
+
+// every .png directly inside `dir`, decoded and named by file stem
+pub(crate) fn read_dir_images(dir: &std::path::Path) -> Result<Vec<(String, RgbaImage)>> {
+    // sorted, so the packing doesn't change between runs
+    let paths: Vec<_> = crate::vfs::list(dir)
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+        })
+        .collect();
+    let mut images = Vec::with_capacity(paths.len());
+    for path in paths {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bytes = crate::vfs::read(&path).map_err(Error::io(&path))?;
+        images.push((name, decode_image(&path, &bytes)?));
+    }
+    Ok(images)
+}
+
+impl Atlas {
+    // pack already-decoded images and upload the pages to `textures`
+    pub fn from_images(
+        gpu: &WGPU,
+        textures: &mut TextureRegistry,
+        images: Vec<(String, RgbaImage)>,
+        options: AtlasOptions,
+    ) -> Result<Self> {
+        Self::build(gpu, textures, images, options, &[])
+    }
+
+    // pack `images`, reusing the ids in `old_pages` for as many pages as they cover and
+    // releasing the rest
+    pub(crate) fn build(
+        gpu: &WGPU,
+        textures: &mut TextureRegistry,
+        images: Vec<(String, RgbaImage)>,
+        options: AtlasOptions,
+        old_pages: &[TextureId],
+    ) -> Result<Self> {
+        let (page_images, placed) = pack(&images, options)?;
+        let pages: Vec<TextureId> = page_images
+            .iter()
+            .enumerate()
+            .map(|(i, page)| {
+                let texture = gpu.texture_from_image(page, Some("atlas"), &options.texture);
+                match old_pages.get(i) {
+                    Some(&id) => {
+                        textures.replace(gpu, id, texture);
+                        id
+                    }
+                    None => textures.insert_with(gpu, texture, options.texture),
+                }
+            })
+            .collect();
+        let spare: Vec<TextureId> = old_pages.iter().skip(pages.len()).copied().collect();
+        for &id in &spare {
+            textures.release(gpu, id);
+        }
+        let mut regions = HashMap::new();
+        for ((name, _), (page, rect)) in images.into_iter().zip(placed) {
+            let (pw, ph) = page_images[page].dimensions();
+            let [x, y, w, h] = rect;
+            regions.insert(
+                name,
+                AtlasRegion {
+                    texture: pages[page],
+                    rect,
+                    sheet_region: [
+                        x as f32 / pw as f32,
+                        y as f32 / ph as f32,
+                        w as f32 / pw as f32,
+                        h as f32 / ph as f32,
+                    ],
+                },
+            );
+        }
+        log::info!("Packed {} images into {} atlas page(s)", regions.len(), pages.len());
+        Ok(Self { regions, pages, spare })
+    }
+
+    // every .png directly inside `dir`, named by file stem
+    pub fn load_dir(
+        gpu: &WGPU,
+        textures: &mut TextureRegistry,
+        dir: &std::path::Path,
+        options: AtlasOptions,
+    ) -> Result<Self> {
+        Self::from_images(gpu, textures, read_dir_images(dir)?, options)
+    }
+
+    // Repack `dir` after one of its images changed. The pages keep their texture ids, but
+    // regions can move, so sheet regions looked up before this need looking up again.
+    // Pages the repack doesn't need any more are released. On error the atlas is left as
+    // it was.
+    pub fn reload_dir(
+        &mut self,
+        gpu: &WGPU,
+        textures: &mut TextureRegistry,
+        dir: &std::path::Path,
+        options: AtlasOptions,
+    ) -> Result<()> {
+        *self = Self::build(gpu, textures, read_dir_images(dir)?, options, &self.page_ids())?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    pub fn sheet_region(&self, name: &str) -> Option<[f32; 4]> {
+        self.regions.get(name).map(|region| region.sheet_region)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(|name| name.as_str())
+    }
+
+    pub fn pages(&self) -> &[TextureId] {
+        &self.pages
+    }
+
+    // every id this atlas holds, released ones included, for a rebuild to reuse
+    pub(crate) fn page_ids(&self) -> Vec<TextureId> {
+        self.pages.iter().chain(&self.spare).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str, w: u32, h: u32) -> (String, RgbaImage) {
+        (name.to_string(), RgbaImage::from_pixel(w, h, image::Rgba([255, 0, 0, 255])))
+    }
+
+    fn options(max_size: u32, padding: u32, extrude: u32) -> AtlasOptions {
+        AtlasOptions {
+            max_size,
+            padding,
+            extrude,
+            ..AtlasOptions::default()
+        }
+    }
+
+    fn overlaps(a: [u32; 4], b: [u32; 4]) -> bool {
+        a[0] < b[0] + b[2] && b[0] < a[0] + a[2] && a[1] < b[1] + b[3] && b[1] < a[1] + a[3]
+    }
+
+    #[test]
+    fn skyline_places_lowest_then_leftmost() {
+        let mut sky = Skyline::new(10);
+        let (i, x, y) = sky.find(4, 3).unwrap();
+        assert_eq!((x, y), (0, 0));
+        sky.place(i, x, y, 4, 3);
+        let (i, x, y) = sky.find(4, 5).unwrap();
+        assert_eq!((x, y), (4, 0));
+        sky.place(i, x, y, 4, 5);
+        // 3 wide only fits above the first rect now
+        assert_eq!(sky.find(3, 2).map(|(_, x, y)| (x, y)), Some((0, 3)));
+        assert_eq!(sky.find(11, 1), None);
+        assert_eq!(sky.used, [8, 5]);
+    }
+
+    #[test]
+    fn packed_images_dont_overlap() {
+        let sizes = [(30, 20), (10, 40), (25, 25), (5, 5), (40, 8), (12, 30), (18, 18), (7, 22)];
+        let images: Vec<_> = sizes.iter().enumerate().map(|(i, &(w, h))| image(&i.to_string(), w, h)).collect();
+        let options = options(64, 2, 1);
+        let (pages, placed) = pack(&images, options).unwrap();
+        let border = options.padding + options.extrude * 2;
+        for (i, &(page, rect)) in placed.iter().enumerate() {
+            assert_eq!([rect[2], rect[3]], [sizes[i].0, sizes[i].1]);
+            // the whole slot, extrusion included, is on the page
+            let (pw, ph) = pages[page].dimensions();
+            assert!(rect[0] + rect[2] + options.extrude <= pw && rect[1] + rect[3] + options.extrude <= ph);
+            for &(other_page, other) in &placed[i + 1..] {
+                let grow = |r: [u32; 4]| [r[0] - options.extrude, r[1] - options.extrude, r[2] + border, r[3] + border];
+                assert!(page != other_page || !overlaps(grow(rect), grow(other)), "{:?} {:?}", rect, other);
+            }
+        }
+    }
+
+    #[test]
+    fn extrude_and_padding_offset_the_image() {
+        let images = vec![image("a", 4, 4), image("b", 4, 4)];
+        let (pages, placed) = pack(&images, options(64, 3, 2)).unwrap();
+        assert_eq!(placed[0], (0, [2, 2, 4, 4]));
+        // the next slot starts after a's extrusion on both sides and the padding
+        assert_eq!(placed[1], (0, [2 + 4 + 2 + 3 + 2, 2, 4, 4]));
+        // the border pixels are copies of the image's edge, the padding is left empty
+        assert_eq!(pages[0].get_pixel(0, 0)[3], 255);
+        assert_eq!(pages[0].get_pixel(8, 0)[3], 0);
+        assert_eq!(pages[0].dimensions(), (2 * (4 + 4 + 3), 4 + 4 + 3));
+    }
+
+    #[test]
+    fn full_page_spills_onto_another() {
+        let images = vec![image("a", 20, 20), image("b", 20, 20), image("c", 20, 20)];
+        let (pages, placed) = pack(&images, options(32, 0, 0)).unwrap();
+        assert_eq!(pages.len(), 3);
+        let pages_used: Vec<usize> = placed.iter().map(|&(page, _)| page).collect();
+        assert_eq!(pages_used, [0, 1, 2]);
+        let images = vec![image("a", 16, 16), image("b", 16, 16), image("c", 32, 16)];
+        let (pages, _) = pack(&images, options(32, 0, 0)).unwrap();
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn image_bigger_than_a_page_is_an_error() {
+        let images = vec![image("small", 8, 8), image("huge", 30, 8)];
+        match pack(&images, options(32, 2, 1)) {
+            Err(Error::Atlas(message)) => assert!(message.contains("huge"), "{}", message),
+            other => panic!("expected an atlas error, got {:?}", other.map(|(pages, _)| pages.len())),
+        }
+    }
+}
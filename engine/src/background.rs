@@ -0,0 +1,75 @@
+use crate::gpu::WGPU;
+use crate::sprite::{GPUCamera, GPUOverlay};
+use crate::texture::{TextureId, TextureRegistry};
+
+// A full-screen layer that repeats a texture across the world, drawn with shader2.wgsl.
+// Load the texture with a Repeat or MirrorRepeat address mode or it will smear at the edges.
+pub struct BackgroundLayer {
+    pub texture: TextureId,
+    // world units covered by one copy of the texture
+    pub tile_size: [f32; 2],
+    // how much the layer moves with the camera: 0 stays put on screen, 1 moves with the
+    // world, in between looks further away
+    pub parallax: [f32; 2],
+    // drift in texture widths/heights per second, e.g. for a starfield
+    pub scroll: [f32; 2],
+    scroll_offset: [f32; 2],
+    uniform: GPUOverlay,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl BackgroundLayer {
+    // `layout` is the group 1 (Overlay) layout of the shader2.wgsl pipeline
+    pub fn new(gpu: &WGPU, layout: &wgpu::BindGroupLayout, texture: TextureId, tile_size: [f32; 2]) -> Self {
+        let uniform = GPUOverlay::default();
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("background"),
+            size: std::mem::size_of::<GPUOverlay>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            texture,
+            tile_size,
+            parallax: [1.0, 1.0],
+            scroll: [0.0, 0.0],
+            scroll_offset: [0.0, 0.0],
+            uniform,
+            buffer,
+            bind_group,
+        }
+    }
+
+    // advance the scroll and line the tiles up with `camera` (the one the sprites are drawn with)
+    pub fn update(&mut self, gpu: &WGPU, dt: f32, camera: GPUCamera) {
+        for axis in 0..2 {
+            self.scroll_offset[axis] = (self.scroll_offset[axis] + self.scroll[axis] * dt).fract();
+        }
+        let [x, y] = camera.screen_pos;
+        let [w, h] = camera.screen_size;
+        let [tw, th] = self.tile_size;
+        // texture v runs down while world y runs up, so v starts from the top of the view
+        self.uniform.uv_scale = [w / tw, h / th];
+        self.uniform.uv_offset = [
+            x * self.parallax[0] / tw + self.scroll_offset[0],
+            -(y * self.parallax[1] + h) / th + self.scroll_offset[1],
+        ];
+        gpu.queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    // the shader2.wgsl pipeline should already be set
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, textures: &'a TextureRegistry) {
+        rpass.set_bind_group(0, textures.bind_group(self.texture), &[]);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::Path;
+use engine::vfs;
+
+// Pack a content directory into one archive, which the games mount by themselves when it
+// sits next to the executable:
+//     cargo run -p engine --bin pack -- content target/release/content.pak
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (dir, out) = match args.as_slice() {
+        [dir] => (dir.as_str(), vfs::ARCHIVE_NAME),
+        [dir, out] => (dir.as_str(), out.as_str()),
+        _ => {
+            eprintln!("usage: pack <content dir> [archive, default {}]", vfs::ARCHIVE_NAME);
+            std::process::exit(2);
+        }
+    };
+    // files keep the paths the games load them by, e.g. content/space.jpeg
+    let prefix = Path::new(dir)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| dir.to_string());
+    match vfs::pack(Path::new(dir), &prefix, Path::new(out)) {
+        Ok(count) => println!("Packed {} files from {} into {}", count, dir, out),
+        Err(e) => {
+            eprintln!("Couldn't pack {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use rand::Rng;
+use crate::input::MousePos;
+use crate::sprite::GPUCamera;
+
+// A camera over world space (the same units as screen_region, y pointing up).
+// It keeps a point of interest on screen, stays inside the level and can shake,
+// then hands the shader a GPUCamera for the part of the world that's visible.
+pub struct Camera {
+    // world point at the middle of the view
+    pub center: [f32; 2],
+    // how much of the world fits on screen at zoom 1
+    pub view_size: [f32; 2],
+    // 2.0 shows half as much world, twice as big
+    pub zoom: f32,
+    // level rect [x, y, w, h] the view never leaves
+    pub bounds: Option<[f32; 4]>,
+    // half-size of the box around the center the target can move in without the camera following
+    pub deadzone: [f32; 2],
+    // how quickly the camera catches up, per second; 0 snaps straight to the target
+    pub follow_rate: f32,
+    shake_strength: f32,
+    shake_duration: f32,
+    shake_left: f32,
+    shake_offset: [f32; 2],
+}
+
+impl Camera {
+    pub fn new(view_size: [f32; 2]) -> Self {
+        Self {
+            center: [view_size[0] / 2.0, view_size[1] / 2.0],
+            view_size,
+            zoom: 1.0,
+            bounds: None,
+            deadzone: [0.0, 0.0],
+            follow_rate: 0.0,
+            shake_strength: 0.0,
+            shake_duration: 0.0,
+            shake_left: 0.0,
+            shake_offset: [0.0, 0.0],
+        }
+    }
+
+    // size of the world area on screen once zoom is applied
+    pub fn visible_size(&self) -> [f32; 2] {
+        [self.view_size[0] / self.zoom, self.view_size[1] / self.zoom]
+    }
+
+    // move toward keeping `target` (a world point) inside the deadzone
+    pub fn follow(&mut self, target: [f32; 2], dt: f32) {
+        let mut desired = self.center;
+        for (axis, desired) in desired.iter_mut().enumerate() {
+            if target[axis] > self.center[axis] + self.deadzone[axis] {
+                *desired = target[axis] - self.deadzone[axis];
+            } else if target[axis] < self.center[axis] - self.deadzone[axis] {
+                *desired = target[axis] + self.deadzone[axis];
+            }
+        }
+        let t = if self.follow_rate <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-self.follow_rate * dt).exp()
+        };
+        for (center, desired) in self.center.iter_mut().zip(desired) {
+            *center += (desired - *center) * t;
+        }
+        self.clamp_to_bounds();
+    }
+
+    // jump straight to `target`, e.g. when a level starts
+    pub fn snap_to(&mut self, target: [f32; 2]) {
+        self.center = target;
+        self.clamp_to_bounds();
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let Some([bx, by, bw, bh]) = self.bounds else {
+            return;
+        };
+        let visible = self.visible_size();
+        let lo = [bx, by];
+        let size = [bw, bh];
+        for axis in 0..2 {
+            let half = visible[axis] / 2.0;
+            if size[axis] <= visible[axis] {
+                // level smaller than the view: keep it centered
+                self.center[axis] = lo[axis] + size[axis] / 2.0;
+            } else {
+                self.center[axis] = self.center[axis].clamp(lo[axis] + half, lo[axis] + size[axis] - half);
+            }
+        }
+    }
+
+    // shake by up to `strength` world units, dying down over `duration` seconds
+    pub fn shake(&mut self, strength: f32, duration: f32) {
+        self.shake_strength = strength;
+        self.shake_duration = duration;
+        self.shake_left = duration;
+    }
+
+    // advance the shake; call once a frame
+    pub fn update(&mut self, dt: f32) {
+        if self.shake_left > 0.0 {
+            self.shake_left = (self.shake_left - dt).max(0.0);
+            let amount = self.shake_strength * self.shake_left / self.shake_duration;
+            let mut rng = rand::thread_rng();
+            self.shake_offset = [rng.gen_range(-1.0..=1.0) * amount, rng.gen_range(-1.0..=1.0) * amount];
+        } else {
+            self.shake_offset = [0.0, 0.0];
+        }
+    }
+
+    // bottom-left corner of the view in world space, shake included
+    pub fn view_pos(&self) -> [f32; 2] {
+        let visible = self.visible_size();
+        [
+            self.center[0] - visible[0] / 2.0 + self.shake_offset[0],
+            self.center[1] - visible[1] / 2.0 + self.shake_offset[1],
+        ]
+    }
+
+    pub fn gpu_camera(&self) -> GPUCamera {
+        GPUCamera {
+            screen_pos: self.view_pos(),
+            screen_size: self.visible_size(),
+        }
+    }
+
+    // screen pixels (y down) to a world point. Input::mouse_pos is already in virtual
+    // screen pixels, so pass the viewport's virtual_size as `window_size` for it
+    pub fn screen_to_world(&self, screen: MousePos<f64>, window_size: [f32; 2]) -> [f32; 2] {
+        let pos = self.view_pos();
+        let visible = self.visible_size();
+        [
+            pos[0] + screen.x as f32 / window_size[0] * visible[0],
+            pos[1] + (1.0 - screen.y as f32 / window_size[1]) * visible[1],
+        ]
+    }
+
+    // a world point to window pixels (y down)
+    pub fn world_to_screen(&self, world: [f32; 2], window_size: [f32; 2]) -> MousePos<f64> {
+        let pos = self.view_pos();
+        let visible = self.visible_size();
+        MousePos {
+            x: ((world[0] - pos[0]) / visible[0] * window_size[0]) as f64,
+            y: ((1.0 - (world[1] - pos[1]) / visible[1]) * window_size[1]) as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_inside_deadzone_leaves_camera_alone() {
+        let mut camera = Camera::new([100.0, 100.0]);
+        camera.deadzone = [10.0, 5.0];
+        camera.follow([58.0, 46.0], 1.0);
+        assert_eq!(camera.center, [50.0, 50.0]);
+    }
+
+    #[test]
+    fn target_outside_deadzone_is_kept_on_its_edge() {
+        let mut camera = Camera::new([100.0, 100.0]);
+        camera.deadzone = [10.0, 5.0];
+        camera.follow([80.0, 20.0], 1.0);
+        assert_eq!(camera.center, [70.0, 25.0]);
+    }
+
+    #[test]
+    fn follow_rate_eases_toward_the_target() {
+        let mut camera = Camera::new([100.0, 100.0]);
+        camera.follow_rate = 2.0;
+        camera.follow([150.0, 50.0], 0.5);
+        // 1 - e^-1 of the way there
+        let expected = 50.0 + 100.0 * (1.0 - (-1.0f32).exp());
+        assert!((camera.center[0] - expected).abs() < 1e-3);
+        assert_eq!(camera.center[1], 50.0);
+    }
+
+    #[test]
+    fn view_stays_inside_bounds() {
+        let mut camera = Camera::new([100.0, 100.0]);
+        camera.bounds = Some([0.0, 0.0, 400.0, 60.0]);
+        camera.follow([1000.0, 1000.0], 1.0);
+        // x stops half a view from the right edge; y is narrower than the view, so centered
+        assert_eq!(camera.center, [350.0, 30.0]);
+        // zoomed in the view is only 50 tall, so y is clamped too
+        camera.zoom = 2.0;
+        camera.snap_to([-50.0, 0.0]);
+        assert_eq!(camera.center, [25.0, 25.0]);
+        assert_eq!(camera.view_pos(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let mut camera = Camera::new([200.0, 100.0]);
+        camera.snap_to([300.0, 80.0]);
+        let window = [800.0, 400.0];
+        let screen = camera.world_to_screen([260.0, 100.0], window);
+        let world = camera.screen_to_world(screen, window);
+        assert!((world[0] - 260.0).abs() < 1e-3 && (world[1] - 100.0).abs() < 1e-3);
+        // y is flipped: the top of the window is the top of the view
+        let top_left = camera.screen_to_world(MousePos { x: 0.0, y: 0.0 }, window);
+        assert_eq!(top_left, [200.0, 130.0]);
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use image::RgbaImage;
+use crate::gpu::WGPU;
+use crate::input::{Input, Key};
+
+struct Recording {
+    frames: Vec<RgbaImage>,
+    fps: u32,
+    // seconds of recording left
+    left: f32,
+    // seconds until the next frame should be grabbed
+    until_next: f32,
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    size: [u32; 2],
+    // rows are padded out to COPY_BYTES_PER_ROW_ALIGNMENT
+    padded_row: u32,
+    bgra: bool,
+}
+
+// Screenshots (PNG) and short recordings (animated GIF) of what the game draws. Files go
+// in `dir`, named by the time they were taken. Each frame: update() before drawing,
+// capture() with the encoder before it's submitted, then finish() after the submit.
+pub struct Capture {
+    pub dir: PathBuf,
+    // recorded frames are shrunk by this; full-size GIFs are huge and slow to encode
+    pub gif_scale: f32,
+    screenshot: bool,
+    recording: Option<Recording>,
+    grab_frame: bool,
+    readback: Option<Readback>,
+    // whether the readback holds this frame, copied in capture()
+    pending: bool,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("captures"),
+            gif_scale: 0.5,
+            screenshot: false,
+            recording: None,
+            grab_frame: false,
+            readback: None,
+            pending: false,
+        }
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_millis())
+}
+
+impl Capture {
+    // save the next frame as a PNG
+    pub fn screenshot(&mut self) {
+        self.screenshot = true;
+    }
+
+    // record the next `seconds` at `fps` frames a second into a GIF, written once it's done
+    pub fn record(&mut self, seconds: f32, fps: u32) {
+        self.recording = Some(Recording {
+            frames: Vec::new(),
+            fps: fps.max(1),
+            left: seconds,
+            until_next: 0.0,
+        });
+    }
+
+    // end a recording early, writing what's been recorded so far
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.left = 0.0;
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // F12 takes a screenshot, F10 starts (or stops) a 5 second recording;
+    // call once a frame before Input::next_frame
+    pub fn handle_hotkeys(&mut self, input: &Input) {
+        if input.is_key_pressed(Key::F12) {
+            self.screenshot();
+        }
+        if input.is_key_pressed(Key::F10) {
+            if self.is_recording() {
+                self.stop_recording();
+            } else {
+                self.record(5.0, 15);
+            }
+        }
+    }
+
+    // work out whether this frame needs reading back
+    pub fn update(&mut self, dt: f32) {
+        self.grab_frame = false;
+        if let Some(recording) = &mut self.recording {
+            recording.until_next -= dt;
+            if recording.until_next <= 0.0 {
+                recording.until_next += 1.0 / recording.fps as f32;
+                // don't try to catch up after a long frame
+                recording.until_next = recording.until_next.max(0.0);
+                self.grab_frame = true;
+            }
+            recording.left -= dt;
+        }
+    }
+
+    // Copy `source` into a readback buffer if this frame is wanted. It needs COPY_SRC
+    // usage, which the swapchain usually doesn't have; Transition::scene_texture() does.
+    pub fn capture(&mut self, gpu: &WGPU, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Texture) {
+        if !self.screenshot && !self.grab_frame {
+            return;
+        }
+        // there's no blocking on the GPU (or a filesystem) in a browser
+        if cfg!(target_arch = "wasm32") {
+            log::warn!("Capturing isn't supported on the web");
+            self.screenshot = false;
+            self.recording = None;
+            return;
+        }
+        let bgra = match source.format() {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            format => {
+                log::warn!("Can't capture a {:?} texture", format);
+                self.screenshot = false;
+                self.recording = None;
+                return;
+            }
+        };
+        let size = [source.width(), source.height()];
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (size[0] * 4).div_ceil(align) * align;
+        if self.readback.as_ref().is_none_or(|r| r.size != size) {
+            let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("capture"),
+                size: (padded_row * size[1]) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            self.readback = Some(Readback {
+                buffer,
+                size,
+                padded_row,
+                bgra,
+            });
+        }
+        if let Some(readback) = &mut self.readback {
+            readback.bgra = bgra;
+            encoder.copy_texture_to_buffer(
+                source.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback.buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(readback.padded_row),
+                        rows_per_image: Some(size[1]),
+                    },
+                },
+                source.size(),
+            );
+            self.pending = true;
+        }
+    }
+
+    // read back what capture() copied (waiting on the GPU) and write any finished files
+    pub fn finish(&mut self, gpu: &WGPU) {
+        if self.pending {
+            self.pending = false;
+            if let Some(img) = self.read(gpu) {
+                if self.screenshot {
+                    self.screenshot = false;
+                    self.save_png(&img);
+                }
+                if self.grab_frame {
+                    if let Some(recording) = &mut self.recording {
+                        let w = ((img.width() as f32 * self.gif_scale) as u32).max(1);
+                        let h = ((img.height() as f32 * self.gif_scale) as u32).max(1);
+                        recording.frames.push(image::imageops::resize(&img, w, h, image::imageops::FilterType::Triangle));
+                    }
+                }
+            }
+        }
+        if self.recording.as_ref().is_some_and(|r| r.left <= 0.0) {
+            if let Some(recording) = self.recording.take() {
+                self.save_gif(recording);
+            }
+        }
+    }
+
+    fn read(&self, gpu: &WGPU) -> Option<RgbaImage> {
+        let readback = self.readback.as_ref()?;
+        let slice = readback.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        gpu.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = receiver.recv().ok()? {
+            log::warn!("Couldn't read back the frame: {}", e);
+            return None;
+        }
+        let [w, h] = readback.size;
+        let mut pixels = Vec::with_capacity((w * h * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(readback.padded_row as usize).take(h as usize) {
+                for px in row[..(w * 4) as usize].chunks_exact(4) {
+                    // the scene is drawn over a transparent clear, so keep it opaque
+                    if readback.bgra {
+                        pixels.extend_from_slice(&[px[2], px[1], px[0], 255]);
+                    } else {
+                        pixels.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                    }
+                }
+            }
+        }
+        readback.buffer.unmap();
+        RgbaImage::from_raw(w, h, pixels)
+    }
+
+    fn path(&self, prefix: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}.{}", prefix, timestamp(), extension))
+    }
+
+    fn save_png(&self, img: &RgbaImage) {
+        let path = self.path("screenshot", "png");
+        match std::fs::create_dir_all(&self.dir).map_err(image::ImageError::IoError).and_then(|_| img.save(&path)) {
+            Ok(()) => log::info!("Saved {}", path.display()),
+            Err(e) => log::warn!("Couldn't save {}: {}", path.display(), e),
+        }
+    }
+
+    // encoding is slow, so it happens on another thread
+    fn save_gif(&self, recording: Recording) {
+        if recording.frames.is_empty() {
+            return;
+        }
+        let path = self.path("recording", "gif");
+        let dir = self.dir.clone();
+        std::thread::spawn(move || match write_gif(&dir, &path, recording) {
+            Ok(()) => log::info!("Saved {}", path.display()),
+            Err(e) => log::warn!("Couldn't save {}: {}", path.display(), e),
+        });
+    }
+}
+
+fn write_gif(dir: &Path, path: &Path, recording: Recording) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::File::create(path)?;
+    let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(std::io::BufWriter::new(file), 10);
+    encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+    let delay = image::Delay::from_numer_denom_ms(1000, recording.fps);
+    encoder.encode_frames(
+        recording
+            .frames
+            .into_iter()
+            .map(|img| image::Frame::from_parts(img, 0, 0, delay)),
+    )?;
+    Ok(())
+}
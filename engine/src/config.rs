@@ -0,0 +1,122 @@
+use winit::event_loop::EventLoop;
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window, WindowBuilder};
+use crate::error::Result;
+use crate::gpu::WGPU;
+use crate::input::{Input, Key};
+use crate::sprite::SpriteOption;
+use crate::viewport::ScaleMode;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FullscreenMode {
+    Windowed,
+    // a borderless window covering the monitor
+    Borderless,
+    // take over the monitor's video mode
+    Exclusive,
+}
+
+// Window and presentation settings for a game. Title, size, resizable and sRGB are read
+// once at startup; fullscreen, vsync and the scale mode can also change while the game runs.
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub title: String,
+    // inner size in physical pixels
+    pub size: [u32; 2],
+    pub resizable: bool,
+    pub fullscreen: FullscreenMode,
+    pub vsync: bool,
+    // used when the surface supports it, otherwise `vsync` decides
+    pub present_mode: Option<wgpu::PresentMode>,
+    // prefer an sRGB swapchain format (the sprite sheet is loaded as sRGB)
+    pub srgb: bool,
+    // how the virtual screen fits into the window
+    pub scale_mode: ScaleMode,
+    // force a sprite buffer strategy instead of letting the adapter decide
+    pub sprite_option: Option<SpriteOption>,
+    // watch the WGSL files and content/ and reload them when they're saved (see hotreload)
+    pub hot_reload: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "unit3game".to_string(),
+            size: [crate::WINDOW_WIDTH as u32, crate::WINDOW_HEIGHT as u32],
+            resizable: true,
+            fullscreen: FullscreenMode::Windowed,
+            vsync: true,
+            present_mode: None,
+            srgb: true,
+            scale_mode: ScaleMode::Fit,
+            sprite_option: None,
+            hot_reload: cfg!(debug_assertions),
+        }
+    }
+}
+
+fn winit_fullscreen(mode: FullscreenMode, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        // winit lists the biggest, fastest modes first
+        FullscreenMode::Exclusive => monitor
+            .and_then(|m| m.video_modes().next())
+            .map(Fullscreen::Exclusive),
+    }
+}
+
+impl AppConfig {
+    pub fn build_window(&self, event_loop: &EventLoop<()>) -> Result<Window> {
+        Ok(WindowBuilder::new()
+            .with_title(&self.title)
+            .with_inner_size(winit::dpi::PhysicalSize::new(self.size[0], self.size[1]))
+            .with_resizable(self.resizable)
+            .with_fullscreen(winit_fullscreen(self.fullscreen, event_loop.primary_monitor()))
+            .build(event_loop)?)
+    }
+
+    pub fn present_mode(&self, caps: &wgpu::SurfaceCapabilities) -> wgpu::PresentMode {
+        match self.present_mode {
+            Some(mode) if caps.present_modes.contains(&mode) => mode,
+            _ if self.vsync => wgpu::PresentMode::AutoVsync,
+            _ => wgpu::PresentMode::AutoNoVsync,
+        }
+    }
+
+    // the first format matching `srgb`, else the first there is; None if there are none
+    pub fn surface_format(&self, caps: &wgpu::SurfaceCapabilities) -> Option<wgpu::TextureFormat> {
+        caps.formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb() == self.srgb)
+            .or_else(|| caps.formats.first().copied())
+    }
+
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode, window: &Window) {
+        self.fullscreen = mode;
+        window.set_fullscreen(winit_fullscreen(mode, window.current_monitor()));
+    }
+
+    // windowed <-> borderless; exclusive fullscreen goes back to a window too
+    pub fn toggle_fullscreen(&mut self, window: &Window) {
+        let mode = match self.fullscreen {
+            FullscreenMode::Windowed => FullscreenMode::Borderless,
+            _ => FullscreenMode::Windowed,
+        };
+        self.set_fullscreen(mode, window);
+    }
+
+    pub fn set_vsync(&mut self, vsync: bool, gpu: &mut WGPU) {
+        self.vsync = vsync;
+        gpu.apply_config(self);
+    }
+
+    // Alt+Enter toggles fullscreen; call once a frame before Input::next_frame
+    pub fn handle_hotkeys(&mut self, input: &Input, window: &Window) {
+        let alt = input.is_key_down(Key::LAlt) || input.is_key_down(Key::RAlt);
+        if alt && input.is_key_pressed(Key::Return) {
+            self.toggle_fullscreen(window);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::gpu::WGPU;
+use crate::input::{Input, Key};
+use crate::sprite::{GPUCamera, GPUSprite, Layer, SpriteBuffer, SpriteList, SpritePipeline};
+use crate::texture::{TextureId, TextureOptions, TextureRegistry};
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// a 3x5 pixel font, each row's bits left to right; lowercase draws as uppercase
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('*', [0b101, 0b010, 0b101, 0b000, 0b000]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('{', [0b011, 0b010, 0b110, 0b010, 0b011]),
+    ('}', [0b110, 0b010, 0b011, 0b010, 0b110]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+];
+
+// each glyph gets a 4x6 cell (one pixel of gap), with a solid white cell after the last
+const CELL: [u32; 2] = [4, 6];
+// how many frames the frame time graph shows
+const HISTORY: usize = 120;
+
+pub const GREEN: [f32; 4] = [0.2, 1.0, 0.3, 1.0];
+pub const RED: [f32; 4] = [1.0, 0.25, 0.2, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+pub const CYAN: [f32; 4] = [0.2, 0.9, 1.0, 1.0];
+pub const GREY: [f32; 4] = [1.0, 1.0, 1.0, 0.25];
+
+// An F3 overlay with FPS, CPU times and sprite counts in the corner, and wireframes the
+// game adds each frame (grid cells, colliders, AI vision) over the world. It's drawn with
+// the sprite pipeline, after everything else. An error set with set_error() shows along
+// the bottom, and a loading bar set with set_loading() in the middle, whether or not the
+// rest is visible.
+pub struct DebugOverlay {
+    pub visible: bool,
+    error: Option<String>,
+    loading: Option<f32>,
+    // pixels per font pixel
+    pub text_scale: f32,
+    texture: TextureId,
+    frame_times: VecDeque<f32>,
+    update_time: f32,
+    render_time: f32,
+    // shapes in world space, cleared every upload
+    world: SpriteList,
+    world_buffer: SpriteBuffer,
+    hud_buffer: SpriteBuffer,
+    hud_camera: wgpu::Buffer,
+}
+
+// smooth a timing so the numbers are readable
+fn smooth(old: f32, new: f32) -> f32 {
+    if old == 0.0 {
+        new
+    } else {
+        old + (new - old) * 0.1
+    }
+}
+
+impl DebugOverlay {
+    pub fn new(gpu: &WGPU, textures: &mut TextureRegistry) -> Self {
+        let width = (GLYPHS.len() as u32 + 1) * CELL[0];
+        let mut img = image::RgbaImage::new(width, CELL[1]);
+        for (i, (_, rows)) in GLYPHS.iter().enumerate() {
+            for (y, bits) in rows.iter().enumerate() {
+                for x in 0..3 {
+                    if bits & (0b100 >> x) != 0 {
+                        img.put_pixel(i as u32 * CELL[0] + x, y as u32, image::Rgba([255; 4]));
+                    }
+                }
+            }
+        }
+        for y in 0..CELL[1] {
+            for x in 0..CELL[0] {
+                img.put_pixel(GLYPHS.len() as u32 * CELL[0] + x, y, image::Rgba([255; 4]));
+            }
+        }
+        let options = TextureOptions::pixel_art();
+        let texture = gpu.texture_from_image(&img, Some("debug font"), &options);
+        let texture = textures.insert_with(gpu, texture, options);
+        let hud_camera = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug camera"),
+            size: std::mem::size_of::<GPUCamera>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            visible: false,
+            error: None,
+            loading: None,
+            text_scale: 3.0,
+            texture,
+            frame_times: VecDeque::with_capacity(HISTORY),
+            update_time: 0.0,
+            render_time: 0.0,
+            world: SpriteList::new(),
+            world_buffer: SpriteBuffer::new(gpu),
+            hud_buffer: SpriteBuffer::new(gpu),
+            hud_camera,
+        }
+    }
+
+    // F3 shows and hides it; call once a frame before Input::next_frame
+    pub fn handle_hotkeys(&mut self, input: &Input) {
+        if input.is_key_pressed(Key::F3) {
+            self.visible = !self.visible;
+        }
+    }
+
+    // once a frame, with the time since the last one
+    pub fn frame(&mut self, dt: f32) {
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+    }
+
+    // CPU time spent on game logic this frame
+    pub fn set_update_time(&mut self, time: Duration) {
+        self.update_time = smooth(self.update_time, time.as_secs_f32());
+    }
+
+    // CPU time spent building and submitting the last frame's draw calls
+    pub fn set_render_time(&mut self, time: Duration) {
+        self.render_time = smooth(self.render_time, time.as_secs_f32());
+    }
+
+    // e.g. a shader that didn't compile; None clears it
+    pub fn set_error(&mut self, error: Option<&str>) {
+        self.error = error.map(str::to_string);
+    }
+
+    // progress from 0 to 1 while assets load (see Assets::progress); None hides the bar
+    pub fn set_loading(&mut self, progress: Option<f32>) {
+        self.loading = progress;
+    }
+
+    fn showing_hud(&self) -> bool {
+        self.visible || self.error.is_some() || self.loading.is_some()
+    }
+
+    fn solid_region(&self) -> [f32; 4] {
+        // the middle of the solid cell, so filtering never reaches a glyph
+        let width = ((GLYPHS.len() as u32 + 1) * CELL[0]) as f32;
+        let x = GLYPHS.len() as f32 * CELL[0] as f32 + 1.0;
+        [x / width, 1.0 / CELL[1] as f32, 2.0 / width, (CELL[1] - 2) as f32 / CELL[1] as f32]
+    }
+
+    fn push(list: &mut SpriteList, texture: TextureId, sprite: GPUSprite) {
+        list.push_textured(Layer::Hud, texture, sprite);
+    }
+
+    // a line between two world points, `width` world units thick
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], width: f32, color: [f32; 4]) {
+        if !self.visible {
+            return;
+        }
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let len = (dx * dx + dy * dy).sqrt();
+        let mid = [(from[0] + to[0]) / 2.0, (from[1] + to[1]) / 2.0];
+        let mut sprite = GPUSprite {
+            screen_region: [mid[0] - len / 2.0, mid[1] - width / 2.0, len, width],
+            sheet_region: self.solid_region(),
+            color,
+            ..Default::default()
+        };
+        sprite.set_rotation(dy.atan2(dx));
+        Self::push(&mut self.world, self.texture, sprite);
+    }
+
+    // the outline of a world rect [x, y, w, h]
+    pub fn rect(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        let [x, y, w, h] = rect;
+        let corners = [[x, y], [x + w, y], [x + w, y + h], [x, y + h]];
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], 2.0, color);
+        }
+    }
+
+    // a filled world rect, e.g. a translucent marker on a grid cell
+    pub fn fill(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        if !self.visible {
+            return;
+        }
+        let sprite = GPUSprite {
+            screen_region: rect,
+            sheet_region: self.solid_region(),
+            color,
+            ..Default::default()
+        };
+        Self::push(&mut self.world, self.texture, sprite);
+    }
+
+    // lines between the cells of a cols x rows grid starting at `origin`
+    pub fn grid(&mut self, origin: [f32; 2], cell: [f32; 2], cols: usize, rows: usize, color: [f32; 4]) {
+        let [x, y] = origin;
+        let (w, h) = (cell[0] * cols as f32, cell[1] * rows as f32);
+        for col in 0..=cols {
+            let cx = x + col as f32 * cell[0];
+            self.line([cx, y], [cx, y + h], 1.0, color);
+        }
+        for row in 0..=rows {
+            let cy = y + row as f32 * cell[1];
+            self.line([x, cy], [x + w, cy], 1.0, color);
+        }
+    }
+
+    pub fn circle(&mut self, center: [f32; 2], radius: f32, color: [f32; 4]) {
+        self.arc(center, radius, 0.0, std::f32::consts::TAU, color);
+    }
+
+    // a vision cone: two edges out from `origin` `half_angle` either side of `direction`
+    // (radians, 0 pointing right and PI/2 up), joined by an arc at `radius`
+    pub fn cone(&mut self, origin: [f32; 2], direction: f32, half_angle: f32, radius: f32, color: [f32; 4]) {
+        let point = |a: f32| [origin[0] + a.cos() * radius, origin[1] + a.sin() * radius];
+        self.line(origin, point(direction - half_angle), 2.0, color);
+        self.line(origin, point(direction + half_angle), 2.0, color);
+        self.arc(origin, radius, direction - half_angle, direction + half_angle, color);
+    }
+
+    fn arc(&mut self, center: [f32; 2], radius: f32, from: f32, to: f32, color: [f32; 4]) {
+        let segments = (((to - from).abs() * radius / 12.0).ceil() as usize).clamp(4, 64);
+        let point = |a: f32| [center[0] + a.cos() * radius, center[1] + a.sin() * radius];
+        for i in 0..segments {
+            let a0 = from + (to - from) * i as f32 / segments as f32;
+            let a1 = from + (to - from) * (i + 1) as f32 / segments as f32;
+            self.line(point(a0), point(a1), 2.0, color);
+        }
+    }
+
+    // text on the virtual screen at `pos` (its top-left corner, y up like the world)
+    fn text(&self, list: &mut SpriteList, pos: [f32; 2], text: &str, scale: f32, color: [f32; 4]) {
+        let width = ((GLYPHS.len() as u32 + 1) * CELL[0]) as f32;
+        let s = scale;
+        for (i, c) in text.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let Some(glyph) = GLYPHS.iter().position(|(g, _)| *g == c) else {
+                continue;
+            };
+            let x = pos[0] + i as f32 * CELL[0] as f32 * s;
+            Self::push(list, self.texture, GPUSprite {
+                screen_region: [x, pos[1] - 5.0 * s, 3.0 * s, 5.0 * s],
+                sheet_region: [(glyph as u32 * CELL[0]) as f32 / width, 0.0, 3.0 / width, 5.0 / CELL[1] as f32],
+                color,
+                ..Default::default()
+            });
+        }
+    }
+
+    // Build the stats panel and send it and this frame's shapes to the GPU. `camera` is the
+    // world camera uniform, `sprites` the game's own SpriteBuffer for its counts.
+    pub fn upload(&mut self, gpu: &WGPU, camera: &wgpu::Buffer, sprites: &SpriteBuffer) {
+        if !self.showing_hud() {
+            self.world.truncate(0);
+            return;
+        }
+        let screen = GPUCamera {
+            screen_pos: [0.0, 0.0],
+            screen_size: [WINDOW_WIDTH, WINDOW_HEIGHT],
+        };
+        gpu.queue.write_buffer(&self.hud_camera, 0, bytemuck::bytes_of(&gpu.viewport.crop_camera(screen)));
+
+        let mut hud = SpriteList::new();
+        if self.visible {
+            self.push_stats(&mut hud, sprites);
+        }
+        if let Some(progress) = self.loading {
+            self.push_loading(&mut hud, progress);
+        }
+        if let Some(error) = &self.error {
+            self.push_error(&mut hud, error);
+        }
+        self.world_buffer.upload(gpu, camera, &self.world);
+        self.hud_buffer.upload(gpu, &self.hud_camera, &hud);
+        self.world.truncate(0);
+    }
+
+    fn push_stats(&self, hud: &mut SpriteList, sprites: &SpriteBuffer) {
+        let s = self.text_scale;
+        let line_height = (CELL[1] as f32 + 1.0) * s;
+        let (left, top) = (8.0, WINDOW_HEIGHT - 8.0);
+        let frame = self.frame_times.back().copied().unwrap_or(0.0);
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+        let fps = if average > 0.0 { 1.0 / average } else { 0.0 };
+        let lines = [
+            format!("FPS {:.0}", fps),
+            format!("FRAME {:.1} MS", frame * 1000.0),
+            format!("UPDATE {:.2} MS", self.update_time * 1000.0),
+            format!("RENDER {:.2} MS", self.render_time * 1000.0),
+            format!("SPRITES {}/{}", sprites.len(), sprites.capacity()),
+            format!("BUFFER {} KB", sprites.capacity() * std::mem::size_of::<GPUSprite>() / 1024),
+        ];
+        let panel_w = 17.0 * CELL[0] as f32 * s;
+        let graph_h = 60.0;
+        let panel_h = lines.len() as f32 * line_height + graph_h + 3.0 * s;
+        Self::push(hud, self.texture, GPUSprite {
+            screen_region: [left - s, top - panel_h, panel_w, panel_h + s],
+            sheet_region: self.solid_region(),
+            color: [0.0, 0.0, 0.0, 0.6],
+            ..Default::default()
+        });
+        for (i, line) in lines.iter().enumerate() {
+            self.text(hud, [left, top - i as f32 * line_height], line, s, GREEN);
+        }
+        // one bar per frame, 33 ms tall, with a line at 60 fps
+        let graph_y = top - panel_h + s;
+        let bar_w = (panel_w - 2.0 * s) / HISTORY as f32;
+        for (i, &dt) in self.frame_times.iter().enumerate() {
+            let h = (dt / (1.0 / 30.0)).min(1.0) * graph_h;
+            let color = if dt > 1.0 / 55.0 { RED } else { GREEN };
+            Self::push(hud, self.texture, GPUSprite {
+                screen_region: [left + i as f32 * bar_w, graph_y, bar_w.max(1.0), h],
+                sheet_region: self.solid_region(),
+                color,
+                ..Default::default()
+            });
+        }
+        Self::push(hud, self.texture, GPUSprite {
+            screen_region: [left, graph_y + graph_h / 2.0, panel_w - 2.0 * s, 1.0],
+            sheet_region: self.solid_region(),
+            color: YELLOW,
+            ..Default::default()
+        });
+    }
+
+    // a bar across the middle of the screen with the percentage above it
+    fn push_loading(&self, hud: &mut SpriteList, progress: f32) {
+        let s = self.text_scale;
+        let progress = progress.clamp(0.0, 1.0);
+        let (w, h) = (WINDOW_WIDTH / 2.0, 4.0 * s);
+        let (x, y) = ((WINDOW_WIDTH - w) / 2.0, (WINDOW_HEIGHT - h) / 2.0);
+        let label = format!("LOADING {:.0}%", progress * 100.0);
+        let label_w = label.len() as f32 * CELL[0] as f32 * s;
+        self.text(hud, [(WINDOW_WIDTH - label_w) / 2.0, y + h + 8.0 * s], &label, s, GREEN);
+        Self::push(hud, self.texture, GPUSprite {
+            screen_region: [x - s, y - s, w + 2.0 * s, h + 2.0 * s],
+            sheet_region: self.solid_region(),
+            color: GREY,
+            ..Default::default()
+        });
+        Self::push(hud, self.texture, GPUSprite {
+            screen_region: [x, y, w * progress, h],
+            sheet_region: self.solid_region(),
+            color: GREEN,
+            ..Default::default()
+        });
+    }
+
+    // the error wrapped across the bottom of the screen, the end cut off if it's very long
+    fn push_error(&self, hud: &mut SpriteList, error: &str) {
+        let s = 2.0;
+        let line_height = (CELL[1] as f32 + 1.0) * s;
+        let per_line = ((WINDOW_WIDTH - 16.0) / (CELL[0] as f32 * s)) as usize;
+        let mut lines: Vec<String> = Vec::new();
+        for text in error.lines() {
+            let chars: Vec<char> = text.chars().collect();
+            lines.extend(chars.chunks(per_line.max(1)).map(|chunk| chunk.iter().collect()));
+        }
+        lines.truncate(16);
+        let height = lines.len() as f32 * line_height + 2.0 * s;
+        Self::push(hud, self.texture, GPUSprite {
+            screen_region: [0.0, 0.0, WINDOW_WIDTH, height + 8.0],
+            sheet_region: self.solid_region(),
+            color: [0.0, 0.0, 0.0, 0.8],
+            ..Default::default()
+        });
+        for (i, line) in lines.iter().enumerate() {
+            self.text(hud, [8.0, height + 4.0 - i as f32 * line_height], line, s, RED);
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a SpritePipeline,
+        textures: &'a TextureRegistry,
+    ) {
+        if self.visible {
+            self.world_buffer.draw(rpass, pipeline, textures);
+        }
+        if self.showing_hud() {
+            self.hud_buffer.draw(rpass, pipeline, textures);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::assets::AssetError;
+
+// Everything the engine can fail at. Setup errors (window, surface, adapter, device) end
+// the game, which should report() them and stop; the rest are worth logging and carrying
+// on without the file.
+#[derive(Debug)]
+pub enum Error {
+    // the OS wouldn't make the window
+    Window(winit::error::OsError),
+    Surface(wgpu::CreateSurfaceError),
+    // the adapter can't present to the window in any format
+    SurfaceFormat,
+    // no GPU (or browser graphics API) that can draw to the window
+    Adapter,
+    Device(wgpu::RequestDeviceError),
+    // the frame to draw into couldn't be had, and reconfiguring won't help
+    Frame(wgpu::SurfaceError),
+    // a file that couldn't be read
+    Io { path: PathBuf, source: io::Error },
+    // an image that couldn't be decoded
+    Texture { path: PathBuf, source: image::ImageError },
+    // images that can't be packed, e.g. one bigger than a page
+    Atlas(String),
+    // no audio output, or a sound that couldn't be decoded or played
+    Audio(String),
+    // a load through Assets that failed
+    Asset(AssetError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // for map_err on a read of `path`
+    pub(crate) fn io(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Window(e) => write!(f, "couldn't create the window: {}", e),
+            Error::Surface(e) => write!(f, "couldn't create a surface for the window: {}", e),
+            Error::SurfaceFormat => write!(f, "the window's surface supports no texture formats"),
+            Error::Adapter => write!(f, "no graphics adapter can draw to this window"),
+            Error::Device(e) => write!(f, "couldn't open the graphics device: {}", e),
+            Error::Frame(e) => write!(f, "couldn't get a frame to draw into: {}", e),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Texture { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Atlas(message) => write!(f, "atlas: {}", message),
+            Error::Audio(message) => write!(f, "audio: {}", message),
+            Error::Asset(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Window(e) => Some(e),
+            Error::Surface(e) => Some(e),
+            Error::Device(e) => Some(e),
+            Error::Frame(e) => Some(e),
+            Error::Io { source, .. } => Some(source),
+            Error::Texture { source, .. } => Some(source),
+            Error::Asset(e) => Some(e),
+            Error::SurfaceFormat | Error::Adapter | Error::Atlas(_) | Error::Audio(_) => None,
+        }
+    }
+}
+
+impl From<winit::error::OsError> for Error {
+    fn from(e: winit::error::OsError) -> Self {
+        Error::Window(e)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(e: wgpu::CreateSurfaceError) -> Self {
+        Error::Surface(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Error::Device(e)
+    }
+}
+
+impl From<AssetError> for Error {
+    fn from(e: AssetError) -> Self {
+        Error::Asset(e)
+    }
+}
+
+// Tell the player about an error the game can't go on from: logged everywhere, and on the
+// web also written into the page in place of the canvas.
+pub fn report(error: &Error) {
+    log::error!("{}", error);
+    #[cfg(target_arch = "wasm32")]
+    {
+        let document = web_sys::window().and_then(|win| win.document());
+        let parent = document.and_then(|doc| {
+            doc.get_element_by_id("game")
+                .or_else(|| doc.body().map(web_sys::Element::from))
+        });
+        if let Some(parent) = parent {
+            parent.set_text_content(Some(&format!("The game had to stop: {}", error)));
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use crate::sprite::SpriteOption;
-use crate::sprite::SPRITES;
+use crate::sprite::{GPUSprite, SpriteOption, SPRITE_UNIFORM_MAX};
+use crate::viewport::Viewport;
+use crate::config::AppConfig;
+use crate::error::{Error, Result};
+use crate::texture::TextureOptions;
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
 use wgpu::BindGroupLayoutEntry;
 
 #[allow(dead_code)]
@@ -10,6 +14,53 @@ pub struct WGPU {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    // how sprite data gets to the vertex shader on this adapter
+    pub sprite_option: SpriteOption,
+    // where the WINDOW_WIDTH x WINDOW_HEIGHT game screen sits in the actual window
+    pub viewport: Viewport,
+}
+
+// an image file's bytes as RGBA, whatever format they're in
+pub(crate) fn decode_image(path: &std::path::Path, bytes: &[u8]) -> Result<image::RgbaImage> {
+    image::load_from_memory(bytes)
+        .map(|img| img.to_rgba8())
+        .map_err(|source| Error::Texture {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn supports_storage(adapter: &wgpu::Adapter) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+        && adapter.limits().max_storage_buffers_per_shader_stage > 0
+}
+
+fn supports_uniform(adapter: &wgpu::Adapter) -> bool {
+    let size = SPRITE_UNIFORM_MAX * std::mem::size_of::<GPUSprite>();
+    adapter.limits().max_uniform_buffer_binding_size as usize >= size
+}
+
+// Storage buffers where the adapter has them in the vertex stage (desktop), then uniform
+// buffers big enough for SPRITE_UNIFORM_MAX sprites (WebGL2), then instanced vertex
+// buffers, which anything can do. A requested option is used if the adapter supports it.
+fn pick_sprite_option(adapter: &wgpu::Adapter, requested: Option<SpriteOption>) -> SpriteOption {
+    match requested {
+        Some(SpriteOption::Storage) if supports_storage(adapter) => return SpriteOption::Storage,
+        Some(SpriteOption::Uniform) if supports_uniform(adapter) => return SpriteOption::Uniform,
+        Some(SpriteOption::VertexBuffer) => return SpriteOption::VertexBuffer,
+        Some(option) => log::warn!("Sprite mode {:?} isn't supported here, picking another", option),
+        None => {}
+    }
+    if supports_storage(adapter) {
+        SpriteOption::Storage
+    } else if supports_uniform(adapter) {
+        SpriteOption::Uniform
+    } else {
+        SpriteOption::VertexBuffer
+    }
 }
 
 impl WGPU {
@@ -17,69 +68,92 @@ impl WGPU {
         &self,
         path: &std::path::Path,
         label: Option<&str>,
-    ) -> Result<(wgpu::Texture, image::RgbaImage), Box<dyn std::error::Error>> {
-        #[cfg(target_arch = "wasm32")]
-        let img = {
-            let fetch = web_sys::window()
-                .map(|win| win.fetch_with_str(path.as_ref().to_str().unwrap()))
-                .unwrap();
-            let resp: web_sys::Response = wasm_bindgen_futures::JsFuture::from(fetch)
-                .await
-                .unwrap()
-                .into();
-            log::debug!("{:?} {:?}", &resp, resp.status());
-            let buf: js_sys::ArrayBuffer =
-                wasm_bindgen_futures::JsFuture::from(resp.array_buffer().unwrap())
-                    .await
-                    .unwrap()
-                    .into();
-            log::debug!("{:?} {:?}", &buf, buf.byte_length());
-            let u8arr = js_sys::Uint8Array::new(&buf);
-            log::debug!("{:?}, {:?}", &u8arr, u8arr.length());
-            let mut bytes = vec![0; u8arr.length() as usize];
-            log::debug!("{:?}", &bytes);
-            u8arr.copy_to(&mut bytes);
-            image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)
-                .map_err(|e| e.to_string())?
-                .to_rgba8()
-        };
-        #[cfg(not(target_arch = "wasm32"))]
-        let img = image::open(path)?.to_rgba8();
+    ) -> Result<(wgpu::Texture, image::RgbaImage)> {
+        let img = self.load_image(path).await?;
+        Ok((self.texture_from_image(&img, label, &TextureOptions::default()), img))
+    }
+
+    pub async fn load_image(&self, path: &std::path::Path) -> Result<image::RgbaImage> {
+        let bytes = crate::vfs::fetch(path).await.map_err(Error::io(path))?;
+        decode_image(path, &bytes)
+    }
+
+    // upload an RGBA image as an sRGB texture that can be sampled, applying the
+    // premultiply and mipmap parts of `options` (the sampler parts are TextureRegistry's)
+    pub fn texture_from_image(
+        &self,
+        img: &image::RgbaImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> wgpu::Texture {
         let (width, height) = img.dimensions();
         let size = wgpu::Extent3d {
             width,
             height,
             depth_or_array_layers: 1,
         };
+        let mip_level_count = if options.mipmaps { size.max_mips(wgpu::TextureDimension::D2) } else { 1 };
         let texture = self.device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
-        self.queue.write_texture(
-            texture.as_image_copy(),
-            &img,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
-            size,
-        );
-        Ok((texture, img))
+        self.write_image(&texture, img, options);
+        texture
     }
 
-    pub async fn new(window: &winit::window::Window) -> Self {
+    // overwrite every mip level of a texture made by texture_from_image with an image of
+    // the same size, e.g. when the file changes on disk
+    pub fn write_image(&self, texture: &wgpu::Texture, img: &image::RgbaImage, options: &TextureOptions) {
+        let mut img = img.clone();
+        if options.premultiply {
+            for pixel in img.pixels_mut() {
+                let a = pixel[3] as u32;
+                for channel in pixel.0.iter_mut().take(3) {
+                    *channel = ((*channel as u32 * a + 127) / 255) as u8;
+                }
+            }
+        }
+        for mip_level in 0..texture.mip_level_count() {
+            if mip_level > 0 {
+                // each level is the last one halved on the CPU
+                let (w, h) = img.dimensions();
+                img = image::imageops::resize(&img, (w / 2).max(1), (h / 2).max(1), image::imageops::FilterType::Triangle);
+            }
+            let (w, h) = img.dimensions();
+            self.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &img,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * w),
+                    rows_per_image: Some(h),
+                },
+                wgpu::Extent3d {
+                    width: w,
+                    height: h,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    pub async fn new(window: &winit::window::Window, app: &AppConfig) -> Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::default();
 
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
@@ -88,7 +162,10 @@ impl WGPU {
                 compatible_surface: Some(&surface),
             })
             .await
-            .expect("Failed to find an appropriate adapter");
+            .ok_or(Error::Adapter)?;
+
+        let sprite_option = pick_sprite_option(&adapter, app.sprite_option);
+        log::info!("Use sprite mode {:?}", sprite_option);
 
         // Create the logical device and command queue
         let (device, queue) = adapter
@@ -96,7 +173,7 @@ impl WGPU {
                 &wgpu::DeviceDescriptor {
                     label: None,
                     features: wgpu::Features::empty(),
-                    limits: if SPRITES == SpriteOption::Storage {
+                    limits: if sprite_option == SpriteOption::Storage {
                         wgpu::Limits::downlevel_defaults()
                     } else {
                         wgpu::Limits::downlevel_webgl2_defaults()
@@ -105,46 +182,64 @@ impl WGPU {
                 },
                 None,
             )
-            .await
-            .expect("Failed to create device");
-
-        if SPRITES == SpriteOption::Storage {
-            let supports_storage_resources = adapter
-                .get_downlevel_capabilities()
-                .flags
-                .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
-                && device.limits().max_storage_buffers_per_shader_stage > 0;
-            assert!(supports_storage_resources, "Storage buffers not supported");
-        }
+            .await?;
+
         let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
+        let swapchain_format = app.surface_format(&swapchain_capabilities).ok_or(Error::SurfaceFormat)?;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode: app.present_mode(&swapchain_capabilities),
             alpha_mode: swapchain_capabilities.alpha_modes[0],
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
+        let viewport = Viewport::new([WINDOW_WIDTH, WINDOW_HEIGHT], app.scale_mode, size);
 
-        Self {
+        Ok(Self {
             instance,
             surface,
             adapter,
             device,
             queue,
             config,
-        }
+            sprite_option,
+            viewport,
+        })
     }
 
     pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.config.width = size.width;
         self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
+        self.viewport.resize(size);
+    }
+
+    // The texture to draw this frame into. None means skip the frame: the surface was lost
+    // or out of date and has been set up again, or the GPU took too long to hand one over.
+    pub fn next_frame(&mut self) -> Result<Option<wgpu::SurfaceTexture>> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                Ok(None)
+            }
+            Err(wgpu::SurfaceError::Timeout) => Ok(None),
+            Err(e) => Err(Error::Frame(e)),
+        }
+    }
+
+    // pick up runtime changes from the app config (vsync, present mode, scale mode).
+    // The swapchain format stays whatever it started as since the pipelines depend on it.
+    pub fn apply_config(&mut self, app: &AppConfig) {
+        let caps = self.surface.get_capabilities(&self.adapter);
+        self.config.present_mode = app.present_mode(&caps);
+        self.surface.configure(&self.device, &self.config);
+        self.viewport.set_mode(app.scale_mode);
     }
 }
 
@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::gpu::WGPU;
+
+// how often the files are checked by either watcher, in seconds
+const CHECK_INTERVAL: f32 = 0.5;
+
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    changed: bool,
+    error: Option<String>,
+}
+
+// Development helper that watches WGSL files on disk (the same ones baked in with
+// include_str!) and rebuilds pipelines from them when they're saved. A shader that fails
+// to compile or validate is reported through error() and the old pipeline stays in use.
+pub struct ShaderWatcher {
+    pub enabled: bool,
+    files: Vec<Watched>,
+    since_check: f32,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ShaderWatcher {
+    // nothing is watched unless `enabled`, e.g. AppConfig::hot_reload
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && !cfg!(target_arch = "wasm32"),
+            files: Vec::new(),
+            since_check: 0.0,
+        }
+    }
+
+    // start watching `path` (relative to where the game runs from); the id goes to reload()
+    pub fn watch(&mut self, path: impl Into<PathBuf>) -> usize {
+        let path = path.into();
+        self.files.push(Watched {
+            modified: modified(&path),
+            path,
+            changed: false,
+            error: None,
+        });
+        self.files.len() - 1
+    }
+
+    // check the files every so often; true if any changed since the last check
+    pub fn poll(&mut self, dt: f32) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.since_check += dt;
+        if self.since_check < CHECK_INTERVAL {
+            return false;
+        }
+        self.since_check = 0.0;
+        let mut any = false;
+        for file in self.files.iter_mut() {
+            let now = modified(&file.path);
+            if now.is_some() && now != file.modified {
+                file.modified = now;
+                file.changed = true;
+                any = true;
+            }
+        }
+        any
+    }
+
+    // If file `id` changed, compile it and hand the module to `build` (which makes the
+    // pipelines). Some(result) if everything validated; None if nothing changed or it
+    // failed, in which case error() says why.
+    pub fn reload<T>(&mut self, gpu: &WGPU, id: usize, build: impl FnOnce(&wgpu::ShaderModule) -> T) -> Option<T> {
+        let file = &mut self.files[id];
+        if !file.changed {
+            return None;
+        }
+        file.changed = false;
+        let source = match std::fs::read_to_string(&file.path) {
+            Ok(source) => source,
+            Err(e) => {
+                file.error = Some(format!("{}: {}", file.path.display(), e));
+                return None;
+            }
+        };
+        // catch the validation errors that would otherwise panic
+        gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: file.path.to_str(),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+        let built = build(&module);
+        match pollster::block_on(gpu.device.pop_error_scope()) {
+            Some(e) => {
+                let message = format!("{}: {}", file.path.display(), e);
+                log::warn!("{}", message);
+                file.error = Some(message);
+                None
+            }
+            None => {
+                log::info!("Reloaded {}", file.path.display());
+                file.error = None;
+                Some(built)
+            }
+        }
+    }
+
+    // the error from the last failed reload of any file, until it's fixed
+    pub fn error(&self) -> Option<&str> {
+        self.files.iter().find_map(|file| file.error.as_deref())
+    }
+}
+
+// Development helper that watches a content directory (and everything under it) so a
+// game can reload the assets that changed: TextureRegistry::reload for images, and
+// Assets::reload for sounds, text files (levels, JSON) and atlases packed from the
+// changed image's directory. Files are only stat'ed every CHECK_INTERVAL.
+pub struct AssetWatcher {
+    pub enabled: bool,
+    root: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    since_check: f32,
+}
+
+// every file under `dir` with when it was last written
+fn scan(dir: &Path, out: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan(&path, out);
+        } else if let Some(time) = modified(&path) {
+            out.insert(path, time);
+        }
+    }
+}
+
+impl AssetWatcher {
+    // `root` as the game loads from it, e.g. "content", so the changed paths match
+    pub fn new(root: impl Into<PathBuf>, enabled: bool) -> Self {
+        let root = root.into();
+        let enabled = enabled && !cfg!(target_arch = "wasm32");
+        let mut modified = HashMap::new();
+        if enabled {
+            scan(&root, &mut modified);
+        }
+        Self {
+            enabled,
+            root,
+            modified,
+            since_check: 0.0,
+        }
+    }
+
+    // the files written or added since the last check, usually none
+    pub fn poll(&mut self, dt: f32) -> Vec<PathBuf> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        self.since_check += dt;
+        if self.since_check < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.since_check = 0.0;
+        let mut now = HashMap::new();
+        scan(&self.root, &mut now);
+        let mut changed: Vec<PathBuf> = now
+            .iter()
+            .filter(|&(path, time)| self.modified.get(path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.sort();
+        self.modified = now;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_watcher_reports_new_and_written_files() {
+        let dir = std::env::temp_dir().join(format!("engine-watch-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("levels")).unwrap();
+        let level = dir.join("levels/level1.txt");
+        std::fs::write(&level, "v1").unwrap();
+        let mut watcher = AssetWatcher::new(&dir, true);
+        assert!(watcher.poll(CHECK_INTERVAL).is_empty());
+
+        // rewritten with an mtime the filesystem can't round onto the old one
+        let file = std::fs::OpenOptions::new().write(true).open(&level).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(10)).unwrap();
+        drop(file);
+        let added = dir.join("levels/level2.txt");
+        std::fs::write(&added, "new").unwrap();
+        // nothing is looked at until CHECK_INTERVAL has passed
+        assert!(watcher.poll(CHECK_INTERVAL / 2.0).is_empty());
+        assert_eq!(watcher.poll(CHECK_INTERVAL / 2.0), vec![level.clone(), added]);
+        assert!(watcher.poll(CHECK_INTERVAL).is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 pub use winit::dpi::PhysicalPosition as MousePos;
 pub use winit::event::VirtualKeyCode as Key;
 use winit::event::{ElementState, MouseButton};
+use crate::viewport::Viewport;
 
 pub struct Input {
     now_keys: Box<[bool]>,
@@ -9,6 +10,7 @@ pub struct Input {
     prev_mouse: Box<[bool]>,
     now_mouse_pos: MousePos<f64>,
     prev_mouse_pos: MousePos<f64>,
+    viewport: Option<Viewport>,
 }
 impl Default for Input {
     fn default() -> Self {
@@ -19,6 +21,7 @@ impl Default for Input {
             prev_mouse: vec![false; 16].into_boxed_slice(),
             now_mouse_pos: MousePos { x: 0.0, y: 0.0 },
             prev_mouse_pos: MousePos { x: 0.0, y: 0.0 },
+            viewport: None,
         }
     }
 }
@@ -58,13 +61,29 @@ impl Input {
         !self.now_mouse[Self::mouse_button_to_usize(mb)]
             && self.prev_mouse[Self::mouse_button_to_usize(mb)]
     }
+    // map mouse positions into the virtual screen; call again whenever the window resizes
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = Some(viewport);
+    }
+    fn to_virtual(&self, pos: MousePos<f64>) -> MousePos<f64> {
+        match self.viewport {
+            Some(viewport) => viewport.window_to_virtual(pos),
+            None => pos,
+        }
+    }
+    // in virtual screen pixels (y down) once a viewport is set, window pixels before that
     pub fn mouse_pos(&self) -> MousePos<f64> {
+        self.to_virtual(self.now_mouse_pos)
+    }
+    pub fn raw_mouse_pos(&self) -> MousePos<f64> {
         self.now_mouse_pos
     }
     pub fn mouse_delta(&self) -> MousePos<f64> {
+        let now = self.to_virtual(self.now_mouse_pos);
+        let prev = self.to_virtual(self.prev_mouse_pos);
         MousePos {
-            x: self.now_mouse_pos.x - self.prev_mouse_pos.x,
-            y: self.now_mouse_pos.y - self.prev_mouse_pos.y,
+            x: now.x - prev.x,
+            y: now.y - prev.y,
         }
     }
     pub fn key_axis(&self, down: Key, up: Key) -> f32 {
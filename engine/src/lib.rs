@@ -1,8 +1,33 @@
 pub mod input;
 pub mod gpu;
 pub mod sprite;
+pub mod texture;
+pub mod atlas;
+pub mod assets;
+pub mod vfs;
+pub mod background;
+pub mod particles;
+pub mod postfx;
+pub mod lighting;
+pub mod transition;
+pub mod capture;
+pub mod debug;
+pub mod hotreload;
 pub mod gamestate; 
 pub mod sound;
+pub mod anim;
+pub mod tween;
+pub mod camera;
+pub mod viewport;
+pub mod config;
+pub mod error;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+pub use config::AppConfig;
+pub use error::{Error, Result};
+// use this rather than std's, which panics in the browser
+pub use instant::Instant;
 
 // get the width and height of the whole game screen
 pub const  WINDOW_WIDTH: f32 = 1024.0;
@@ -0,0 +1,539 @@
+use std::borrow::Cow;
+use bytemuck::{Pod, Zeroable};
+use crate::gpu::WGPU;
+use crate::sprite::GPUCamera;
+
+// how many lights the shader takes; matches the lights array in lighting.wgsl
+pub const MAX_LIGHTS: usize = 32;
+
+// A light in world space. With the default cone it shines all around; narrow the cone
+// for a flashlight pointing along `direction`.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub position: [f32; 2],
+    // world units to where it fades out completely
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    // radians, 0 pointing right and PI/2 up
+    pub direction: f32,
+    // half the cone's width in radians; PI or more lights every direction
+    pub cone: f32,
+    // radians over which the cone's edge fades out
+    pub cone_softness: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            radius: 200.0,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            direction: 0.0,
+            cone: std::f32::consts::PI,
+            cone_softness: 0.15,
+        }
+    }
+}
+
+impl Light {
+    // cosines of the outer and inner edges of the cone, as the shader wants them
+    fn cone_cos(&self) -> [f32; 2] {
+        if self.cone >= std::f32::consts::PI {
+            // below -1, so every direction is fully inside
+            [-2.0, -1.5]
+        } else {
+            let outer = (self.cone + self.cone_softness / 2.0).min(std::f32::consts::PI);
+            let inner = (self.cone - self.cone_softness / 2.0).max(0.0);
+            [outer.cos(), inner.cos().max(outer.cos() + 0.0001)]
+        }
+    }
+
+    // how much of this light reaches `point` ignoring shadows, per channel
+    fn reach(&self, point: [f32; 2]) -> [f32; 3] {
+        let to_p = [point[0] - self.position[0], point[1] - self.position[1]];
+        let dist = (to_p[0] * to_p[0] + to_p[1] * to_p[1]).sqrt();
+        if dist >= self.radius {
+            return [0.0; 3];
+        }
+        let falloff = 1.0 - dist / self.radius;
+        let cone = if dist > 0.0001 {
+            let [outer, inner] = self.cone_cos();
+            let along = (to_p[0] * self.direction.cos() + to_p[1] * self.direction.sin()) / dist;
+            smoothstep(outer, inner, along)
+        } else {
+            1.0
+        };
+        let k = self.intensity * falloff * falloff * cone;
+        [self.color[0] * k, self.color[1] * k, self.color[2] * k]
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Which cells of a level grid block light, e.g. walls and shelves. Row 0 is the bottom
+// row, matching world y pointing up.
+#[derive(Clone, Debug)]
+pub struct OccluderGrid {
+    // world position of the bottom-left corner of cell (0, 0)
+    pub origin: [f32; 2],
+    pub cell_size: [f32; 2],
+    cols: usize,
+    rows: usize,
+    cells: Vec<bool>,
+}
+
+impl OccluderGrid {
+    pub fn new(origin: [f32; 2], cell_size: [f32; 2], cols: usize, rows: usize) -> Self {
+        Self {
+            origin,
+            cell_size,
+            cols,
+            rows,
+            cells: vec![false; cols * rows],
+        }
+    }
+
+    pub fn size(&self) -> [usize; 2] {
+        [self.cols, self.rows]
+    }
+
+    pub fn set(&mut self, col: usize, row: usize, blocked: bool) {
+        if col < self.cols && row < self.rows {
+            self.cells[row * self.cols + col] = blocked;
+        }
+    }
+
+    // cells outside the grid never block
+    pub fn is_blocked(&self, col: i32, row: i32) -> bool {
+        col >= 0
+            && row >= 0
+            && (col as usize) < self.cols
+            && (row as usize) < self.rows
+            && self.cells[row as usize * self.cols + col as usize]
+    }
+
+    // Mark every cell whose center is inside the world rect [x, y, w, h], so a sprite a
+    // little bigger than its cell doesn't spill into the neighbours.
+    pub fn fill_rect(&mut self, rect: [f32; 4]) {
+        let [x, y, w, h] = rect;
+        for row in 0..self.rows {
+            let cy = self.origin[1] + (row as f32 + 0.5) * self.cell_size[1];
+            if cy < y || cy > y + h {
+                continue;
+            }
+            for col in 0..self.cols {
+                let cx = self.origin[0] + (col as f32 + 0.5) * self.cell_size[0];
+                if cx >= x && cx <= x + w {
+                    self.cells[row * self.cols + col] = true;
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(false);
+    }
+
+    pub fn cell_of(&self, point: [f32; 2]) -> [i32; 2] {
+        [
+            ((point[0] - self.origin[0]) / self.cell_size[0]).floor() as i32,
+            ((point[1] - self.origin[1]) / self.cell_size[1]).floor() as i32,
+        ]
+    }
+
+    // half a cell, so a ray can't skip over a cell diagonally by much
+    fn step(&self) -> f32 {
+        self.cell_size[0].min(self.cell_size[1]) * 0.5
+    }
+
+    // whether light gets from `from` to `to`; the same march as clear_line in lighting.wgsl,
+    // so the end cells don't count
+    pub fn line_clear(&self, from: [f32; 2], to: [f32; 2]) -> bool {
+        let start = self.cell_of(from);
+        let end = self.cell_of(to);
+        let d = [to[0] - from[0], to[1] - from[1]];
+        let len = (d[0] * d[0] + d[1] * d[1]).sqrt();
+        let steps = ((len / self.step()).ceil() as i32).min(256);
+        (1..steps).all(|i| {
+            let t = i as f32 / steps as f32;
+            let c = self.cell_of([from[0] + d[0] * t, from[1] + d[1] * t]);
+            c == start || c == end || !self.is_blocked(c[0], c[1])
+        })
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.cells.iter().map(|&blocked| if blocked { 255 } else { 0 }).collect()
+    }
+}
+
+// Lighting::light_from and light_at without the GPU side
+fn shadowed(light: &Light, occluders: &OccluderGrid, point: [f32; 2]) -> [f32; 3] {
+    let reach = light.reach(point);
+    if reach == [0.0; 3] || !occluders.line_clear(point, light.position) {
+        return [0.0; 3];
+    }
+    reach
+}
+
+fn light_at(ambient: [f32; 3], lights: &[Light], occluders: &OccluderGrid, point: [f32; 2]) -> [f32; 3] {
+    let mut total = ambient;
+    for light in lights.iter().take(MAX_LIGHTS) {
+        let [r, g, b] = shadowed(light, occluders, point);
+        total[0] += r;
+        total[1] += g;
+        total[2] += b;
+    }
+    total.map(|c| c.min(1.0))
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct GPULight {
+    pos_radius: [f32; 4],
+    color: [f32; 4],
+    cone: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct GPULighting {
+    camera: [f32; 4],
+    grid: [f32; 4],
+    counts: [f32; 4],
+    ambient: [f32; 4],
+    lights: [GPULight; MAX_LIGHTS],
+}
+
+// A darkness overlay with point and cone lights whose shadows come from an OccluderGrid.
+// draw() multiplies the light over everything drawn before it in the pass, so draw the
+// world, then this, then the HUD. light_level() answers the same question on the CPU,
+// e.g. whether a guard can see the player.
+pub struct Lighting {
+    // light everywhere gets with no lights; [1, 1, 1] switches the darkness off
+    pub ambient: [f32; 3],
+    // only the first MAX_LIGHTS are drawn
+    pub lights: Vec<Light>,
+    occluders: OccluderGrid,
+    grid_dirty: bool,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    grid_texture: wgpu::Texture,
+}
+
+impl Lighting {
+    pub fn new(gpu: &WGPU, occluders: OccluderGrid) -> Self {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("lighting"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("lighting.wgsl"))),
+        });
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // read with textureLoad, so no sampler
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lighting"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                // scene * light, leaving alpha alone
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gpu.config.format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Dst,
+                            dst_factor: wgpu::BlendFactor::Zero,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let uniform = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lighting"),
+            size: std::mem::size_of::<GPULighting>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (grid_texture, bind_group) = Self::create_grid(gpu, &layout, &uniform, occluders.size());
+        Self {
+            ambient: [0.1, 0.1, 0.15],
+            lights: Vec::new(),
+            occluders,
+            grid_dirty: true,
+            layout,
+            pipeline,
+            uniform,
+            bind_group,
+            grid_texture,
+        }
+    }
+
+    fn create_grid(
+        gpu: &WGPU,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &wgpu::Buffer,
+        [cols, rows]: [usize; 2],
+    ) -> (wgpu::Texture, wgpu::BindGroup) {
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("occluders"),
+            size: wgpu::Extent3d {
+                width: cols.max(1) as u32,
+                height: rows.max(1) as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+        });
+        (texture, bind_group)
+    }
+
+    pub fn occluders(&self) -> &OccluderGrid {
+        &self.occluders
+    }
+
+    // change which cells block light; goes to the GPU on the next update()
+    pub fn occluders_mut(&mut self) -> &mut OccluderGrid {
+        self.grid_dirty = true;
+        &mut self.occluders
+    }
+
+    // light reaching `point` from light `index` alone, shadows included, per channel
+    pub fn light_from(&self, index: usize, point: [f32; 2]) -> [f32; 3] {
+        shadowed(&self.lights[index], &self.occluders, point)
+    }
+
+    // all the light at `point`, ambient included, per channel
+    pub fn light_at(&self, point: [f32; 2]) -> [f32; 3] {
+        light_at(self.ambient, &self.lights, &self.occluders, point)
+    }
+
+    // how lit `point` is, 0 (pitch dark) to 1, going by its brightest channel
+    pub fn light_level(&self, point: [f32; 2]) -> f32 {
+        let [r, g, b] = self.light_at(point);
+        r.max(g).max(b)
+    }
+
+    // send the lights and (if they changed) the occluders; `camera` is the one the world is drawn with
+    pub fn update(&mut self, gpu: &WGPU, camera: GPUCamera) {
+        if self.grid_dirty {
+            let [cols, rows] = self.occluders.size();
+            if [self.grid_texture.width(), self.grid_texture.height()] != [cols.max(1) as u32, rows.max(1) as u32] {
+                (self.grid_texture, self.bind_group) = Self::create_grid(gpu, &self.layout, &self.uniform, [cols, rows]);
+            }
+            if cols > 0 && rows > 0 {
+                gpu.queue.write_texture(
+                    self.grid_texture.as_image_copy(),
+                    &self.occluders.bytes(),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(cols as u32),
+                        rows_per_image: Some(rows as u32),
+                    },
+                    self.grid_texture.size(),
+                );
+            }
+            self.grid_dirty = false;
+        }
+        let mut lights = [GPULight::zeroed(); MAX_LIGHTS];
+        let count = self.lights.len().min(MAX_LIGHTS);
+        for (gpu_light, light) in lights.iter_mut().zip(&self.lights) {
+            let [outer, inner] = light.cone_cos();
+            *gpu_light = GPULight {
+                pos_radius: [light.position[0], light.position[1], light.radius, light.intensity],
+                color: [light.color[0], light.color[1], light.color[2], 0.0],
+                cone: [light.direction.cos(), light.direction.sin(), outer, inner],
+            };
+        }
+        let [cols, rows] = self.occluders.size();
+        let uniform = GPULighting {
+            camera: [camera.screen_pos[0], camera.screen_pos[1], camera.screen_size[0], camera.screen_size[1]],
+            grid: [
+                self.occluders.origin[0],
+                self.occluders.origin[1],
+                self.occluders.cell_size[0],
+                self.occluders.cell_size[1],
+            ],
+            counts: [cols as f32, rows as f32, count as f32, self.occluders.step()],
+            ambient: [self.ambient[0], self.ambient[1], self.ambient[2], 0.0],
+            lights,
+        };
+        gpu.queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    // sets its own pipeline; SpriteBuffer::draw sets the sprite one again
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // 10x10 cells of 10 world units from the origin
+    fn grid() -> OccluderGrid {
+        OccluderGrid::new([0.0, 0.0], [10.0, 10.0], 10, 10)
+    }
+
+    fn level(lights: &[Light], occluders: &OccluderGrid, point: [f32; 2]) -> f32 {
+        let [r, g, b] = light_at([0.0; 3], lights, occluders, point);
+        r.max(g).max(b)
+    }
+
+    #[test]
+    fn fill_rect_takes_cells_by_their_centers() {
+        let mut grid = grid();
+        // covers the centers of columns 1 and 2 in row 0, just misses column 3's
+        grid.fill_rect([8.0, 0.0, 26.0, 9.0]);
+        let blocked: Vec<i32> = (0..10).filter(|&col| grid.is_blocked(col, 0)).collect();
+        assert_eq!(blocked, vec![1, 2]);
+        assert!(!grid.is_blocked(1, 1));
+        assert!(!grid.is_blocked(-1, 0) && !grid.is_blocked(10, 0));
+        grid.clear();
+        assert!(!grid.is_blocked(1, 0));
+    }
+
+    #[test]
+    fn walls_block_the_line() {
+        let mut grid = grid();
+        // a wall down column 5
+        grid.fill_rect([50.0, 0.0, 10.0, 100.0]);
+        assert!(!grid.line_clear([15.0, 15.0], [85.0, 85.0]));
+        assert!(!grid.line_clear([15.0, 55.0], [85.0, 55.0]));
+        assert!(grid.line_clear([15.0, 15.0], [15.0, 85.0]));
+        // the cells the line starts and ends in don't count
+        assert!(grid.line_clear([55.0, 15.0], [15.0, 15.0]));
+    }
+
+    #[test]
+    fn falls_off_to_nothing_at_the_radius() {
+        let light = Light {
+            position: [50.0, 50.0],
+            radius: 40.0,
+            ..Light::default()
+        };
+        let grid = grid();
+        assert_eq!(level(&[light], &grid, [50.0, 50.0]), 1.0);
+        // quadratic falloff: halfway out is a quarter
+        assert!((level(&[light], &grid, [70.0, 50.0]) - 0.25).abs() < 1e-5);
+        assert_eq!(level(&[light], &grid, [90.0, 50.0]), 0.0);
+        assert_eq!(level(&[light], &grid, [50.0, 95.0]), 0.0);
+        // ambient is added and the total capped at 1
+        assert_eq!(light_at([0.5; 3], &[light], &grid, [50.0, 50.0]), [1.0; 3]);
+        assert_eq!(light_at([0.5; 3], &[light], &grid, [95.0, 50.0]), [0.5; 3]);
+    }
+
+    #[test]
+    fn cone_lights_only_its_side() {
+        // pointing up, 45 degrees either side, fading over 0.2 radians
+        let light = Light {
+            position: [50.0, 50.0],
+            radius: 100.0,
+            direction: PI / 2.0,
+            cone: PI / 4.0,
+            cone_softness: 0.2,
+            ..Light::default()
+        };
+        let grid = grid();
+        let at = |angle: f32| level(&[light], &grid, [50.0 + 20.0 * angle.cos(), 50.0 + 20.0 * angle.sin()]);
+        let full = at(PI / 2.0);
+        assert!((full - 0.64).abs() < 1e-5);
+        // inside the soft edge it's still full, past it nothing, and in between it fades
+        assert!((at(PI / 2.0 + PI / 4.0 - 0.11) - full).abs() < 1e-5);
+        assert_eq!(at(PI / 2.0 + PI / 4.0 + 0.11), 0.0);
+        let edge = at(PI / 2.0 - PI / 4.0);
+        assert!(edge > 0.0 && edge < full);
+        assert_eq!(at(-PI / 2.0), 0.0);
+    }
+
+    #[test]
+    fn a_wall_shadows_the_far_side() {
+        let mut grid = grid();
+        grid.fill_rect([50.0, 0.0, 10.0, 100.0]);
+        let light = Light {
+            position: [25.0, 55.0],
+            radius: 100.0,
+            ..Light::default()
+        };
+        assert!(level(&[light], &grid, [35.0, 55.0]) > 0.0);
+        assert_eq!(level(&[light], &grid, [75.0, 55.0]), 0.0);
+        // a second light on the other side reaches it
+        let other = Light {
+            position: [85.0, 55.0],
+            ..light
+        };
+        assert!(level(&[light, other], &grid, [75.0, 55.0]) > 0.0);
+    }
+}
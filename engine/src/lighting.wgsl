@@ -0,0 +1,101 @@
+// Darkness and lights over the world, multiplied onto whatever is already drawn.
+// Lighting in lighting.rs fills the uniform and mirrors light() on the CPU for light_level().
+
+struct Light {
+    // x, y, radius, intensity
+    pos_radius: vec4<f32>,
+    // rgb; a unused
+    color: vec4<f32>,
+    // direction x, y, then the cosines of the cone's outer and inner edges
+    cone: vec4<f32>,
+}
+
+struct Lighting {
+    // GPUCamera: screen_pos then screen_size
+    camera: vec4<f32>,
+    // world position of the grid's bottom-left corner, then the size of one cell
+    grid: vec4<f32>,
+    // columns, rows, number of lights, ray march step in world units
+    counts: vec4<f32>,
+    // rgb everywhere gets even with no lights; a unused
+    ambient: vec4<f32>,
+    lights: array<Light, 32>,
+}
+
+@group(0) @binding(0)
+var<uniform> lighting: Lighting;
+// one texel per grid cell, > 0.5 where a cell blocks light
+@group(0) @binding(1)
+var t_grid: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world: vec2<f32>,
+}
+
+// one triangle that covers the screen, like postfx.wgsl, carrying the world point under each corner
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    let world = lighting.camera.xy + vec2<f32>(uv.x, 1. - uv.y) * lighting.camera.zw;
+    return VertexOutput(
+        vec4<f32>(uv.x * 2. - 1., 1. - uv.y * 2., 0., 1.),
+        world
+    );
+}
+
+fn cell_of(p: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(floor((p - lighting.grid.xy) / lighting.grid.zw));
+}
+
+fn blocked(cell: vec2<i32>) -> bool {
+    let size = vec2<i32>(lighting.counts.xy);
+    if cell.x < 0 || cell.y < 0 || cell.x >= size.x || cell.y >= size.y {
+        return false;
+    }
+    return textureLoad(t_grid, cell, 0).r > 0.5;
+}
+
+// March from p to l. The cells at either end don't count, so shelves light up on the
+// side facing a light and lights can sit inside a wall.
+fn clear_line(p: vec2<f32>, l: vec2<f32>) -> bool {
+    let start = cell_of(p);
+    let end = cell_of(l);
+    let d = l - p;
+    let steps = min(i32(ceil(length(d) / lighting.counts.w)), 256);
+    for (var i = 1; i < steps; i++) {
+        let c = cell_of(p + d * (f32(i) / f32(steps)));
+        if any(c != start) && any(c != end) && blocked(c) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn light(p: vec2<f32>, l: Light) -> vec3<f32> {
+    let to_p = p - l.pos_radius.xy;
+    let dist = length(to_p);
+    let radius = l.pos_radius.z;
+    if dist >= radius {
+        return vec3<f32>(0.);
+    }
+    let falloff = 1. - dist / radius;
+    var cone = 1.;
+    if dist > 0.0001 {
+        cone = smoothstep(l.cone.z, l.cone.w, dot(to_p / dist, l.cone.xy));
+    }
+    if cone <= 0. || !clear_line(p, l.pos_radius.xy) {
+        return vec3<f32>(0.);
+    }
+    return l.color.rgb * l.pos_radius.w * falloff * falloff * cone;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var total = lighting.ambient.rgb;
+    let count = i32(lighting.counts.z);
+    for (var i = 0; i < count; i++) {
+        total += light(in.world, lighting.lights[i]);
+    }
+    return vec4<f32>(min(total, vec3<f32>(1.)), 1.);
+}
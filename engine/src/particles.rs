@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use rand::Rng;
+use crate::gpu::WGPU;
+use crate::sprite::{GPUSprite, Layer, SpriteList};
+use crate::texture::{TextureId, TextureRegistry};
+
+// frames of the texture made by particle_texture: a soft round dot and a solid square
+pub const DOT_FRAME: [f32; 4] = [0.0, 0.0, 0.5, 1.0];
+pub const SQUARE_FRAME: [f32; 4] = [0.5 + 1.0 / 32.0, 1.0 / 16.0, 0.5 - 2.0 / 32.0, 1.0 - 2.0 / 16.0];
+
+// A small white texture for particles that don't come from a sprite sheet; tint them
+// with EmitterConfig::colors. Use DOT_FRAME or SQUARE_FRAME as the frame.
+pub fn particle_texture(gpu: &WGPU, textures: &mut TextureRegistry) -> TextureId {
+    let img = image::RgbaImage::from_fn(32, 16, |x, y| {
+        if x >= 16 {
+            return image::Rgba([255, 255, 255, 255]);
+        }
+        let dx = x as f32 + 0.5 - 8.0;
+        let dy = y as f32 + 0.5 - 8.0;
+        let falloff = (1.0 - (dx * dx + dy * dy).sqrt() / 8.0).clamp(0.0, 1.0);
+        image::Rgba([255, 255, 255, (falloff.sqrt() * 255.0) as u8])
+    });
+    let texture = gpu.texture_from_image(&img, Some("particles"), &Default::default());
+    textures.insert(gpu, texture)
+}
+
+// What an emitter spits out. Pairs like [min, max] pick a random value in between
+// for each particle.
+#[derive(Clone, Debug)]
+pub struct EmitterConfig {
+    // particles per second while the emitter is on; 0 for bursts only
+    pub rate: f32,
+    // older particles are dropped past this
+    pub max_particles: usize,
+    // seconds
+    pub lifetime: [f32; 2],
+    // half-size of the box around the emitter's position particles start in
+    pub spawn_area: [f32; 2],
+    // direction in radians, 0 pointing right and PI/2 up
+    pub angle: [f32; 2],
+    // world units per second
+    pub speed: [f32; 2],
+    // added to the velocity every second (y up, so negative falls)
+    pub gravity: [f32; 2],
+    // radians per second
+    pub spin: [f32; 2],
+    // [w, h] at birth; shrinks or grows to size * end_scale at death
+    pub size: [f32; 2],
+    pub end_scale: f32,
+    // each particle starts as one of these
+    pub colors: Vec<[f32; 4]>,
+    // the color at death; None keeps the starting color and fades its alpha to 0
+    pub end_color: Option<[f32; 4]>,
+    // sheet regions, one picked at random per particle
+    pub frames: Vec<[f32; 4]>,
+    pub texture: TextureId,
+    pub layer: Layer,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            max_particles: 256,
+            lifetime: [0.5, 1.0],
+            spawn_area: [0.0, 0.0],
+            angle: [0.0, std::f32::consts::TAU],
+            speed: [50.0, 100.0],
+            gravity: [0.0, 0.0],
+            spin: [0.0, 0.0],
+            size: [8.0, 8.0],
+            end_scale: 1.0,
+            colors: vec![[1.0, 1.0, 1.0, 1.0]],
+            end_color: None,
+            frames: vec![DOT_FRAME],
+            texture: TextureId::default(),
+            layer: Layer::Hud,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    pos: [f32; 2],
+    vel: [f32; 2],
+    age: f32,
+    lifetime: f32,
+    rotation: f32,
+    spin: f32,
+    color: [f32; 4],
+    frame: [f32; 4],
+}
+
+fn pick(rng: &mut impl Rng, [lo, hi]: [f32; 2]) -> f32 {
+    if hi > lo {
+        rng.gen_range(lo..hi)
+    } else {
+        lo
+    }
+}
+
+fn lerp4(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+pub struct Emitter {
+    pub config: EmitterConfig,
+    // world point particles come out of
+    pub position: [f32; 2],
+    // whether `rate` is spawning; bursts work either way
+    pub emitting: bool,
+    // fractional particles owed by `rate`
+    pending: f32,
+    // oldest first, so the cap drops from the front
+    particles: VecDeque<Particle>,
+}
+
+impl Emitter {
+    pub fn new(config: EmitterConfig) -> Self {
+        Self {
+            config,
+            position: [0.0, 0.0],
+            emitting: false,
+            pending: 0.0,
+            particles: VecDeque::new(),
+        }
+    }
+
+    // spawn `count` particles right away at `position`
+    pub fn burst(&mut self, count: usize) {
+        if self.config.max_particles == 0 {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..count {
+            while self.particles.len() >= self.config.max_particles {
+                self.particles.pop_front();
+            }
+            let c = &self.config;
+            let angle = pick(&mut rng, c.angle);
+            let speed = pick(&mut rng, c.speed);
+            let color = if c.colors.is_empty() {
+                [1.0, 1.0, 1.0, 1.0]
+            } else {
+                c.colors[rng.gen_range(0..c.colors.len())]
+            };
+            let frame = if c.frames.is_empty() {
+                DOT_FRAME
+            } else {
+                c.frames[rng.gen_range(0..c.frames.len())]
+            };
+            self.particles.push_back(Particle {
+                pos: [
+                    self.position[0] + pick(&mut rng, [-c.spawn_area[0], c.spawn_area[0]]),
+                    self.position[1] + pick(&mut rng, [-c.spawn_area[1], c.spawn_area[1]]),
+                ],
+                vel: [angle.cos() * speed, angle.sin() * speed],
+                age: 0.0,
+                lifetime: pick(&mut rng, c.lifetime).max(0.001),
+                rotation: 0.0,
+                spin: pick(&mut rng, c.spin),
+                color,
+                frame,
+            });
+        }
+    }
+
+    pub fn burst_at(&mut self, position: [f32; 2], count: usize) {
+        self.position = position;
+        self.burst(count);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.emitting && self.config.rate > 0.0 {
+            self.pending += self.config.rate * dt;
+            let count = self.pending as usize;
+            self.pending -= count as f32;
+            self.burst(count);
+        }
+        let gravity = self.config.gravity;
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            p.vel[0] += gravity[0] * dt;
+            p.vel[1] += gravity[1] * dt;
+            p.pos[0] += p.vel[0] * dt;
+            p.pos[1] += p.vel[1] * dt;
+            p.rotation += p.spin * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.pending = 0.0;
+    }
+
+    // add a sprite per live particle to `list`, on the configured layer and texture
+    pub fn push_sprites(&self, list: &mut SpriteList) {
+        let c = &self.config;
+        for p in &self.particles {
+            let t = p.age / p.lifetime;
+            let scale = 1.0 + (c.end_scale - 1.0) * t;
+            let (w, h) = (c.size[0] * scale, c.size[1] * scale);
+            let end = c.end_color.unwrap_or([p.color[0], p.color[1], p.color[2], 0.0]);
+            let mut sprite = GPUSprite {
+                screen_region: [p.pos[0] - w / 2.0, p.pos[1] - h / 2.0, w, h],
+                sheet_region: p.frame,
+                color: lerp4(p.color, end, t),
+                ..Default::default()
+            };
+            sprite.set_rotation(p.rotation);
+            list.push_textured(c.layer, c.texture, sprite);
+        }
+    }
+}
+
+// a set of emitters updated and drawn together; add() hands back an index for emitter()
+#[derive(Default)]
+pub struct ParticleSystem {
+    emitters: Vec<Emitter>,
+}
+
+impl ParticleSystem {
+    pub fn add(&mut self, emitter: Emitter) -> usize {
+        self.emitters.push(emitter);
+        self.emitters.len() - 1
+    }
+
+    pub fn emitter(&mut self, index: usize) -> &mut Emitter {
+        &mut self.emitters[index]
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for emitter in self.emitters.iter_mut() {
+            emitter.update(dt);
+        }
+    }
+
+    pub fn push_sprites(&self, list: &mut SpriteList) {
+        for emitter in &self.emitters {
+            emitter.push_sprites(list);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for emitter in self.emitters.iter_mut() {
+            emitter.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emitter(max_particles: usize) -> Emitter {
+        Emitter::new(EmitterConfig {
+            max_particles,
+            lifetime: [10.0, 10.0],
+            ..EmitterConfig::default()
+        })
+    }
+
+    #[test]
+    fn cap_drops_the_oldest_particles() {
+        let mut emitter = emitter(3);
+        emitter.burst(3);
+        emitter.update(0.5);
+        emitter.burst(2);
+        assert_eq!(emitter.len(), 3);
+        let ages: Vec<f32> = emitter.particles.iter().map(|p| p.age).collect();
+        assert_eq!(ages, [0.5, 0.0, 0.0]);
+        // a burst bigger than the cap keeps only the newest
+        emitter.burst(10);
+        assert_eq!(emitter.len(), 3);
+        assert!(emitter.particles.iter().all(|p| p.age == 0.0));
+    }
+
+    #[test]
+    fn zero_cap_spawns_nothing() {
+        let mut emitter = emitter(0);
+        emitter.burst(5);
+        emitter.emitting = true;
+        emitter.config.rate = 100.0;
+        emitter.update(1.0);
+        assert!(emitter.is_empty());
+    }
+
+    #[test]
+    fn particles_die_after_their_lifetime() {
+        let mut emitter = emitter(8);
+        emitter.burst(4);
+        emitter.update(9.9);
+        assert_eq!(emitter.len(), 4);
+        emitter.update(0.2);
+        assert!(emitter.is_empty());
+    }
+}
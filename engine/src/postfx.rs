@@ -0,0 +1,348 @@
+use std::borrow::Cow;
+use bytemuck::{Pod, Zeroable};
+use crate::gpu::WGPU;
+use crate::texture::{TextureId, TextureRegistry};
+
+// One full-screen pass, applied in the order they appear in PostFx::effects.
+#[derive(Clone, Copy, Debug)]
+pub enum Effect {
+    // darken toward the corners; radius and softness are fractions of the center-to-corner distance
+    Vignette { strength: f32, radius: f32, softness: f32 },
+    // brightness is added (0 = unchanged), contrast and saturation multiply (1 = unchanged),
+    // then the result is multiplied by tint
+    ColorGrade { brightness: f32, contrast: f32, saturation: f32, tint: [f32; 3] },
+    // a color lookup strip (size*size wide, size tall, blue picking the tile), mixed in by amount
+    Lut { texture: TextureId, size: u32, amount: f32 },
+    // scanlines darkness 0..1 and a slight tube bulge (0 is flat)
+    Crt { scanlines: f32, curvature: f32 },
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct GPUParams {
+    a: [f32; 4],
+    color: [f32; 4],
+}
+
+// a color laid over the picture with its alpha going from `from` to `to`
+#[derive(Clone, Copy, Debug)]
+struct Flash {
+    color: [f32; 3],
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Flash {
+    fn alpha(&self) -> f32 {
+        let t = if self.duration > 0.0 { (self.elapsed / self.duration).min(1.0) } else { 1.0 };
+        self.from + (self.to - self.from) * t
+    }
+}
+
+struct Target {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// Optional render-to-texture stage. While any effect or flash is active the scene is
+// drawn into scene_view() instead of the swapchain, then apply() runs the effect chain
+// into the real frame. With nothing active it costs nothing and the scene draws directly.
+pub struct PostFx {
+    pub effects: Vec<Effect>,
+    flash: Option<Flash>,
+    format: wgpu::TextureFormat,
+    size: [u32; 2],
+    source_layout: wgpu::BindGroupLayout,
+    params_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    targets: Vec<Target>,
+    // one uniform per pass, since every write lands before the passes run
+    params: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    vignette: wgpu::RenderPipeline,
+    grade: wgpu::RenderPipeline,
+    lut: wgpu::RenderPipeline,
+    crt: wgpu::RenderPipeline,
+    flash_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostFx {
+    // `textures` supplies the layout LUT textures are bound with
+    pub fn new(gpu: &WGPU, textures: &TextureRegistry) -> Self {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("postfx"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("postfx.wgsl"))),
+        });
+        let source_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let params_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&source_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let lut_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&source_layout, &params_layout, &textures.layout],
+            push_constant_ranges: &[],
+        });
+        let format = gpu.config.format;
+        let pipeline = |layout: &wgpu::PipelineLayout, entry_point: &str| {
+            gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let vignette = pipeline(&layout, "fs_vignette");
+        let grade = pipeline(&layout, "fs_grade");
+        let lut = pipeline(&lut_layout, "fs_lut");
+        let crt = pipeline(&layout, "fs_crt");
+        let flash_pipeline = pipeline(&layout, "fs_flash");
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            effects: Vec::new(),
+            flash: None,
+            format,
+            size: [0, 0],
+            source_layout,
+            params_layout,
+            sampler,
+            targets: Vec::new(),
+            params: Vec::new(),
+            vignette,
+            grade,
+            lut,
+            crt,
+            flash_pipeline,
+        }
+    }
+
+    // flash `color` over the screen and fade it away over `duration` seconds (a hit, a pickup)
+    pub fn flash(&mut self, color: [f32; 3], duration: f32) {
+        self.fade(color, 1.0, 0.0, duration);
+    }
+
+    // lay `color` over the screen with its alpha going from `from` to `to`; it stays at `to`
+    // afterwards unless that's 0, so fade(black, 0, 1, t) leaves the screen black
+    pub fn fade(&mut self, color: [f32; 3], from: f32, to: f32, duration: f32) {
+        self.flash = Some(Flash {
+            color,
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    pub fn clear_flash(&mut self) {
+        self.flash = None;
+    }
+
+    fn passes(&self) -> usize {
+        self.effects.len() + usize::from(self.flash.is_some())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.passes() > 0
+    }
+
+    // advance the flash and get targets and uniforms ready for this frame; call before drawing
+    pub fn update(&mut self, gpu: &WGPU, dt: f32) {
+        if let Some(flash) = &mut self.flash {
+            flash.elapsed += dt;
+            if flash.elapsed >= flash.duration && flash.to <= 0.0 {
+                self.flash = None;
+            }
+        }
+        let size = [gpu.config.width.max(1), gpu.config.height.max(1)];
+        if self.is_active() && (self.size != size || self.targets.is_empty()) {
+            self.targets = (0..2).map(|_| self.create_target(gpu, size)).collect();
+            self.size = size;
+        }
+        while self.params.len() < self.passes() {
+            let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("postfx params"),
+                size: std::mem::size_of::<GPUParams>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.params_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            });
+            self.params.push((buffer, bind_group));
+        }
+    }
+
+    fn create_target(&self, gpu: &WGPU, size: [u32; 2]) -> Target {
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("postfx target"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        Target { view, bind_group }
+    }
+
+    // Where the scene should be drawn this frame: the offscreen target while effects are
+    // active, otherwise `frame` (the swapchain view) itself.
+    pub fn scene_view<'a>(&'a self, frame: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        match self.targets.first() {
+            Some(target) if self.is_active() => &target.view,
+            _ => frame,
+        }
+    }
+
+    fn pass_params(&self, pass: usize) -> GPUParams {
+        match self.effects.get(pass) {
+            Some(&Effect::Vignette { strength, radius, softness }) => GPUParams {
+                a: [strength, radius, softness.max(0.0001), 0.0],
+                color: [1.0; 4],
+            },
+            Some(&Effect::ColorGrade { brightness, contrast, saturation, tint }) => GPUParams {
+                a: [brightness, contrast, saturation, 0.0],
+                color: [tint[0], tint[1], tint[2], 1.0],
+            },
+            Some(&Effect::Lut { size, amount, .. }) => GPUParams {
+                a: [size as f32, amount, 0.0, 0.0],
+                color: [1.0; 4],
+            },
+            // a scanline every 3 pixels
+            Some(&Effect::Crt { scanlines, curvature }) => GPUParams {
+                a: [scanlines, curvature, self.size[1] as f32 / 3.0, 0.0],
+                color: [1.0; 4],
+            },
+            // the flash always runs last
+            None => {
+                let (color, alpha) = self.flash.map_or(([0.0; 3], 0.0), |flash| (flash.color, flash.alpha()));
+                GPUParams {
+                    a: [0.0; 4],
+                    color: [color[0], color[1], color[2], alpha],
+                }
+            }
+        }
+    }
+
+    // run the effect chain from the scene into `frame`; does nothing when nothing is active
+    pub fn apply(
+        &self,
+        gpu: &WGPU,
+        encoder: &mut wgpu::CommandEncoder,
+        textures: &TextureRegistry,
+        frame: &wgpu::TextureView,
+    ) {
+        let passes = self.passes();
+        if passes == 0 || self.targets.len() < 2 || self.params.len() < passes {
+            return;
+        }
+        for pass in 0..passes {
+            let (buffer, params_bind_group) = &self.params[pass];
+            gpu.queue.write_buffer(buffer, 0, bytemuck::bytes_of(&self.pass_params(pass)));
+            // ping-pong between the two targets, the last pass drawing into the frame
+            let source = &self.targets[pass % 2];
+            let output = if pass + 1 == passes { frame } else { &self.targets[(pass + 1) % 2].view };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("postfx"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            match self.effects.get(pass) {
+                Some(Effect::Vignette { .. }) => rpass.set_pipeline(&self.vignette),
+                Some(Effect::ColorGrade { .. }) => rpass.set_pipeline(&self.grade),
+                Some(Effect::Lut { texture, .. }) => {
+                    rpass.set_pipeline(&self.lut);
+                    rpass.set_bind_group(2, textures.bind_group(*texture), &[]);
+                }
+                Some(Effect::Crt { .. }) => rpass.set_pipeline(&self.crt),
+                None => rpass.set_pipeline(&self.flash_pipeline),
+            }
+            rpass.set_bind_group(0, &source.bind_group, &[]);
+            rpass.set_bind_group(1, params_bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
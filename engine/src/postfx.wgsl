@@ -0,0 +1,96 @@
+// Full-screen effect passes. Each pass reads the previous image (group 0) and writes
+// a new one; PostFx in postfx.rs picks the fragment entry point for each effect.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// one triangle that covers the screen; the parts off screen get clipped
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return VertexOutput(
+        vec4<f32>(uv.x * 2. - 1., 1. - uv.y * 2., 0., 1.),
+        uv
+    );
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+// what a and color mean depends on the effect; see Effect in postfx.rs
+struct Params {
+    a: vec4<f32>,
+    color: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> params: Params;
+
+// a.x strength, a.y radius where darkening starts, a.z how far it takes to reach full strength
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    let src = textureSample(t_source, s_source, in.tex_coords);
+    let d = distance(in.tex_coords, vec2<f32>(0.5, 0.5)) * 1.41421356;
+    let shade = smoothstep(params.a.y, params.a.y + params.a.z, d) * params.a.x;
+    return vec4<f32>(src.rgb * (1. - shade), src.a);
+}
+
+// a.x brightness (added), a.y contrast, a.z saturation; color.rgb multiplies
+@fragment
+fn fs_grade(in: VertexOutput) -> @location(0) vec4<f32> {
+    let src = textureSample(t_source, s_source, in.tex_coords);
+    var rgb = src.rgb + vec3<f32>(params.a.x);
+    rgb = (rgb - vec3<f32>(0.5)) * params.a.y + vec3<f32>(0.5);
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, params.a.z);
+    return vec4<f32>(clamp(rgb * params.color.rgb, vec3<f32>(0.), vec3<f32>(1.)), src.a);
+}
+
+@group(2) @binding(0)
+var t_lut: texture_2d<f32>;
+@group(2) @binding(1)
+var s_lut: sampler;
+
+// The LUT is a size x size x size cube laid out as a strip of size tiles, blue picking
+// the tile (size*size wide, size tall). a.x size, a.y how much of the graded color to use.
+@fragment
+fn fs_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let src = textureSample(t_source, s_source, in.tex_coords);
+    let size = params.a.x;
+    let c = clamp(src.rgb, vec3<f32>(0.), vec3<f32>(1.));
+    let blue = c.b * (size - 1.);
+    let tile0 = floor(blue);
+    let tile1 = min(tile0 + 1., size - 1.);
+    // stay half a texel inside each tile so the tiles don't bleed into each other
+    let x = (c.r * (size - 1.) + 0.5) / (size * size);
+    let y = (c.g * (size - 1.) + 0.5) / size;
+    let graded0 = textureSample(t_lut, s_lut, vec2<f32>(x + tile0 / size, y)).rgb;
+    let graded1 = textureSample(t_lut, s_lut, vec2<f32>(x + tile1 / size, y)).rgb;
+    let graded = mix(graded0, graded1, blue - tile0);
+    return vec4<f32>(mix(src.rgb, graded, params.a.y), src.a);
+}
+
+// a.x scanline darkness, a.y screen curvature, a.z number of scanlines
+@fragment
+fn fs_crt(in: VertexOutput) -> @location(0) vec4<f32> {
+    // bulge the picture like a tube, leaving the corners black
+    let centered = in.tex_coords * 2. - vec2<f32>(1.);
+    let bent = centered * (1. + params.a.y * dot(centered, centered));
+    let uv = bent * 0.5 + vec2<f32>(0.5);
+    let inside = step(0., uv.x) * step(uv.x, 1.) * step(0., uv.y) * step(uv.y, 1.);
+    let src = textureSample(t_source, s_source, clamp(uv, vec2<f32>(0.), vec2<f32>(1.)));
+    let line = 0.5 + 0.5 * cos(uv.y * params.a.z * 6.2831853);
+    let shade = 1. - params.a.x * line;
+    return vec4<f32>(src.rgb * shade * inside, src.a);
+}
+
+// color.rgb mixed over the picture by color.a
+@fragment
+fn fs_flash(in: VertexOutput) -> @location(0) vec4<f32> {
+    let src = textureSample(t_source, s_source, in.tex_coords);
+    return vec4<f32>(mix(src.rgb, params.color.rgb, params.color.a), src.a);
+}
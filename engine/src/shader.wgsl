@@ -16,69 +16,81 @@ struct Camera {
 
 struct GPUSprite {
     to_rect:vec4<f32>,
-    from_rect:vec4<f32>
+    from_rect:vec4<f32>,
+    // rgba multiplied into the texture color
+    color:vec4<f32>,
+    // x: rotation in radians, yz: pivot as a fraction of to_rect's size, w: flip bits (1 = x, 2 = y)
+    transform:vec4<f32>
 }
 
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 @group(0) @binding(1)
 var<storage, read> s_sprites: array<GPUSprite>;
-//const SPRITE_MAX:u32 = u32(512); // 16K / 4*4*2
+//const SPRITE_MAX:u32 = u32(256); // 16K / 4*4*4
 @group(0) @binding(1)
-var<uniform> u_sprites: array<GPUSprite, 512>;
+var<uniform> u_sprites: array<GPUSprite, 256>;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
 }
 
-@vertex
-fn vs_storage_main(@builtin(vertex_index) in_vertex_index: u32, @builtin(instance_index) sprite_index:u32) -> VertexOutput {
+// All three ways of getting sprite data in end up here
+fn sprite_vertex(in_vertex_index: u32, sprite: GPUSprite) -> VertexOutput {
     // We'll just look up the vertex data in those constant arrays
-    let corner:vec4<f32> = vec4(s_sprites[sprite_index].to_rect.xy,0.,1.);
-    let size:vec2<f32> = s_sprites[sprite_index].to_rect.zw;
-    let tex_corner:vec2<f32> = s_sprites[sprite_index].from_rect.xy;
-    let tex_size:vec2<f32> = s_sprites[sprite_index].from_rect.zw;
+    let corner:vec2<f32> = sprite.to_rect.xy;
+    let size:vec2<f32> = sprite.to_rect.zw;
+    let tex_corner:vec2<f32> = sprite.from_rect.xy;
+    let tex_size:vec2<f32> = sprite.from_rect.zw;
     let which_vtx:vec2<f32> = VERTICES[in_vertex_index];
-    let which_uv: vec2<f32> = vec2(VERTICES[in_vertex_index].x, 1.0 - VERTICES[in_vertex_index].y);
+    var which_uv: vec2<f32> = vec2(VERTICES[in_vertex_index].x, 1.0 - VERTICES[in_vertex_index].y);
+    // Flipping just mirrors which part of the sheet each corner samples
+    let flip:u32 = u32(sprite.transform.w);
+    if (flip & 1u) != 0u {
+        which_uv.x = 1.0 - which_uv.x;
+    }
+    if (flip & 2u) != 0u {
+        which_uv.y = 1.0 - which_uv.y;
+    }
+    // Spin the corner around the pivot, then move it into place
+    let pivot:vec2<f32> = sprite.transform.yz * size;
+    let local:vec2<f32> = which_vtx * size - pivot;
+    let c:f32 = cos(sprite.transform.x);
+    let s:f32 = sin(sprite.transform.x);
+    let rotated:vec2<f32> = vec2(local.x * c - local.y * s, local.x * s + local.y * c) + pivot;
+    let world:vec4<f32> = vec4(corner + rotated, 0., 1.);
     return VertexOutput(
-        ((corner + vec4(which_vtx*size,0.,0.) - vec4(camera.screen_pos,0.,0.)) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
-        tex_corner + which_uv*tex_size
+        ((world - vec4(camera.screen_pos,0.,0.)) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
+        tex_corner + which_uv*tex_size,
+        sprite.color
     );
 }
 
+@vertex
+fn vs_storage_main(@builtin(vertex_index) in_vertex_index: u32, @builtin(instance_index) sprite_index:u32) -> VertexOutput {
+    return sprite_vertex(in_vertex_index, s_sprites[sprite_index]);
+}
+
 @vertex
 fn vs_uniform_main(@builtin(vertex_index) in_vertex_index: u32, @builtin(instance_index) sprite_index:u32) -> VertexOutput {
-    // We'll just look up the vertex data in those constant arrays
-    let corner:vec4<f32> = vec4(u_sprites[sprite_index].to_rect.xy,0.,1.);
-    let size:vec2<f32> = u_sprites[sprite_index].to_rect.zw;
-    let tex_corner:vec2<f32> = u_sprites[sprite_index].from_rect.xy;
-    let tex_size:vec2<f32> = u_sprites[sprite_index].from_rect.zw;
-    let which_vtx:vec2<f32> = VERTICES[in_vertex_index];
-    let which_uv: vec2<f32> = vec2(VERTICES[in_vertex_index].x, 1.0 - VERTICES[in_vertex_index].y);
-    return VertexOutput(
-        ((corner + vec4(which_vtx*size,0.,0.) - vec4(camera.screen_pos,0.,0.)) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
-        tex_corner + which_uv*tex_size
-    );
+    return sprite_vertex(in_vertex_index, u_sprites[sprite_index]);
 }
 
 struct InstanceInput {
     @location(0) to_rect: vec4<f32>,
     @location(1) from_rect: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) transform: vec4<f32>,
 };
 
 @vertex
 fn vs_vbuf_main(@builtin(vertex_index) in_vertex_index: u32, sprite_data:InstanceInput) -> VertexOutput {
     // We'll still just look up the vertex positions in those constant arrays
-    let corner:vec4<f32> = vec4(sprite_data.to_rect.xy,0.,1.);
-    let size:vec2<f32> = sprite_data.to_rect.zw;
-    let tex_corner:vec2<f32> = sprite_data.from_rect.xy;
-    let tex_size:vec2<f32> = sprite_data.from_rect.zw;
-    let which_vtx:vec2<f32> = VERTICES[in_vertex_index];
-    let which_uv: vec2<f32> = vec2(VERTICES[in_vertex_index].x, 1.0 - VERTICES[in_vertex_index].y);
-    return VertexOutput(
-        ((corner + vec4(which_vtx*size,0.,0.) - vec4(camera.screen_pos,0.,0.)) / vec4(camera.screen_size/2., 1.0, 1.0)) - vec4(1.0, 1.0, 0.0, 0.0),
-        tex_corner + which_uv*tex_size
+    return sprite_vertex(
+        in_vertex_index,
+        GPUSprite(sprite_data.to_rect, sprite_data.from_rect, sprite_data.color, sprite_data.transform)
     );
 }
 
@@ -98,8 +110,19 @@ fn fs_main(in:VertexOutput) -> @location(0) vec4<f32> {
     // And we use the tex coords from the vertex output to sample from the texture.
     let color:vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     if color.w < 0.2 { discard; }
-    return color;
+    // Then tint it; the pipeline blends, so a lower alpha fades the sprite out
+    return color * in.color;
 }
+
+// for textures loaded with `premultiply`, drawn with premultiplied blending: the texture's
+// rgb already carries its alpha, so the tint's alpha scales all four channels
+@fragment
+fn fs_premultiplied(in:VertexOutput) -> @location(0) vec4<f32> {
+    let color:vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if color.w < 0.2 { discard; }
+    return color * vec4<f32>(in.color.rgb * in.color.a, in.color.a);
+}
+
 @vertex
 fn vs_mainn(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
     let x = f32(i32(in_vertex_index) - 1);
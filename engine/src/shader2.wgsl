@@ -29,13 +29,29 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
 }
 
+// Where the image sits on screen, in the same -1..1 units as VERTICES.
+// An offset of (0,0) and scale of (1,1) covers the whole screen; an offset of (0,2) is
+// one screen up, so tweening it toward (0,0) slides the image in from the top.
+// A scale above 1 lets the image hang off the edges when the window crops the game screen.
+// uv_scale and uv_offset do the same to the texture coordinates, so with a repeating
+// sampler a uv_scale of (4,3) tiles the image 4 across and 3 down (background layers).
+struct Overlay {
+    offset: vec2<f32>,
+    scale: vec2<f32>,
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>
+}
+
+@group(1) @binding(0)
+var<uniform> overlay: Overlay;
+
 // vs_main now produces an instance of that struct...
 @vertex
 fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
     // We'll just look up the vertex data in those constant arrays
     return VertexOutput(
-        VERTICES[in_vertex_index],
-        TEX_COORDS[in_vertex_index]
+        VERTICES[in_vertex_index] * vec4<f32>(overlay.scale, 1., 1.) + vec4<f32>(overlay.offset, 0., 0.),
+        TEX_COORDS[in_vertex_index] * overlay.uv_scale + overlay.uv_offset
     );
 }
 
@@ -1,4 +1,5 @@
-use std::{thread,time};
+use std::cell::RefCell;
+use std::path::Path;
 use kira::{
 	manager::{
 		AudioManager, AudioManagerSettings,
@@ -6,27 +7,53 @@ use kira::{
 	},
 	sound::static_sound::{StaticSoundData, StaticSoundSettings},
 };
+use crate::error::{Error, Result};
 
-pub fn play_sound() {
-    let mut sound_manager =
-        AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).unwrap();
+thread_local! {
+    // made once and kept, so sounds play to the end without holding up the frame; if
+    // there's no audio output the error is kept instead, so it isn't retried every sound
+    static MANAGER: RefCell<Option<std::result::Result<AudioManager<DefaultBackend>, String>>> = const { RefCell::new(None) };
+}
 
-    let sound_data =
-        StaticSoundData::from_file("content/player_hit.ogg", 
-        StaticSoundSettings::default()).unwrap();
+// Start audio output if it isn't already. Browsers only allow that in response to the
+// player doing something, so games call this on their first key press or click; play()
+// also calls it, which is all that's needed elsewhere.
+pub fn unlock() -> Result<()> {
+    MANAGER.with(|manager| {
+        let mut manager = manager.borrow_mut();
+        let manager = manager.get_or_insert_with(|| {
+            AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).map_err(|e| e.to_string())
+        });
+        match manager {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::Audio(e.clone())),
+        }
+    })
+}
 
-    let _ = sound_manager.play(sound_data.clone());
-    thread::sleep(time::Duration::from_millis(10));
+// play sound data that's already loaded (e.g. through Assets)
+pub fn play(sound_data: &StaticSoundData) -> Result<()> {
+    unlock()?;
+    MANAGER.with(|manager| match manager.borrow_mut().as_mut() {
+        Some(Ok(manager)) => manager
+            .play(sound_data.clone())
+            .map(|_| ())
+            .map_err(|e| Error::Audio(e.to_string())),
+        _ => Ok(()),
+    })
 }
 
-pub fn winner_sound() {
-    let mut sound_manager =
-        AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).unwrap();
+// through the vfs, so it works wherever the game is run from
+pub fn load(path: &Path) -> Result<StaticSoundData> {
+    let bytes = crate::vfs::read(path).map_err(Error::io(path))?;
+    StaticSoundData::from_cursor(std::io::Cursor::new(bytes), StaticSoundSettings::default())
+        .map_err(|e| Error::Audio(format!("{}: {}", path.display(), e)))
+}
 
-    let sound_data =
-        StaticSoundData::from_file("content/winneris.ogg", 
-        StaticSoundSettings::default()).unwrap();
+pub fn play_sound() -> Result<()> {
+    play(&load(Path::new("content/player_hit.ogg"))?)
+}
 
-    let _ = sound_manager.play(sound_data.clone());
-    thread::sleep(time::Duration::from_millis(3500));
-}
\ No newline at end of file
+pub fn winner_sound() -> Result<()> {
+    play(&load(Path::new("content/winneris.ogg"))?)
+}
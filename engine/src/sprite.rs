@@ -1,4 +1,7 @@
 use bytemuck::{Pod, Zeroable};
+use std::ops::{Deref, DerefMut, Range, RangeBounds};
+use crate::gpu::{WGPU, CAMERALAYOUT};
+use crate::texture::{TextureId, TextureOptions, TextureRegistry};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SpriteOption {
@@ -11,6 +14,53 @@ pub enum SpriteOption {
 pub struct GPUSprite {
     pub screen_region: [f32; 4],
     pub sheet_region: [f32; 4],
+    // rgba multiplied into the sheet color, so [1,1,1,1] draws it as-is
+    pub color: [f32; 4],
+    // [rotation in radians, pivot x, pivot y, flip bits]; the pivot is a fraction of
+    // screen_region's size and the flip bits are FLIP_X | FLIP_Y stored as a float
+    pub transform: [f32; 4],
+}
+
+pub const FLIP_X: u32 = 1;
+pub const FLIP_Y: u32 = 2;
+
+// how many sprites fit in a 16K uniform buffer (the WebGL2 limit); matches u_sprites in shader.wgsl
+pub const SPRITE_UNIFORM_MAX: usize = 256;
+
+impl Default for GPUSprite {
+    fn default() -> Self {
+        Self {
+            screen_region: [0.0; 4],
+            sheet_region: [0.0; 4],
+            color: [1.0, 1.0, 1.0, 1.0],
+            transform: [0.0, 0.5, 0.5, 0.0],
+        }
+    }
+}
+
+impl GPUSprite {
+    // one vec4 per field in order, for the VertexBuffer sprite option's instance buffer
+    pub const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4, 3 => Float32x4];
+
+    pub fn set_flip(&mut self, flip_x: bool, flip_y: bool) {
+        let mut bits = 0;
+        if flip_x {
+            bits |= FLIP_X;
+        }
+        if flip_y {
+            bits |= FLIP_Y;
+        }
+        self.transform[3] = bits as f32;
+    }
+
+    pub fn set_rotation(&mut self, radians: f32) {
+        self.transform[0] = radians;
+    }
+
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.color[3] = alpha;
+    }
 }
 
 #[repr(C)]
@@ -20,11 +70,518 @@ pub struct GPUCamera {
     pub screen_size: [f32; 2],
 }
 
-#[cfg(all(not(feature = "uniforms"), not(feature = "vbuf")))]
-pub const SPRITES: SpriteOption = SpriteOption::Storage;
-#[cfg(feature = "uniforms")]
-pub const SPRITES: SpriteOption = SpriteOption::Uniform;
-#[cfg(feature = "vbuf")]
-pub const SPRITES: SpriteOption = SpriteOption::VertexBuffer;
-#[cfg(all(feature = "vbuf", feature = "uniform"))]
-compile_error!("Can't choose both vbuf and uniform sprite features");
\ No newline at end of file
+// which band a sprite draws in: later layers draw on top, and sprites on the same
+// layer keep the order they were pushed in
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Layer {
+    Background,
+    Floor,
+    Shelves,
+    Items,
+    Characters,
+    Hud,
+}
+
+// the game's sprites plus a layer and texture for each one. Game logic still indexes
+// sprites by creation order (it derefs to a slice); only what goes to the GPU gets sorted.
+#[derive(Clone, Default)]
+pub struct SpriteList {
+    sprites: Vec<GPUSprite>,
+    layers: Vec<Layer>,
+    textures: Vec<TextureId>,
+}
+
+// a run of sprites in draw order on one layer that all use one texture, so one bind + draw
+#[derive(Clone, Debug)]
+pub struct SpriteBatch {
+    pub layer: Layer,
+    pub texture: TextureId,
+    pub range: Range<usize>,
+}
+
+impl SpriteList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // uses the default (first registered) texture
+    pub fn push(&mut self, layer: Layer, sprite: GPUSprite) {
+        self.push_textured(layer, TextureId::default(), sprite);
+    }
+
+    pub fn push_textured(&mut self, layer: Layer, texture: TextureId, sprite: GPUSprite) {
+        self.sprites.push(sprite);
+        self.layers.push(layer);
+        self.textures.push(texture);
+    }
+
+    pub fn layer(&self, index: usize) -> Layer {
+        self.layers[index]
+    }
+
+    pub fn set_layer(&mut self, index: usize, layer: Layer) {
+        self.layers[index] = layer;
+    }
+
+    pub fn texture(&self, index: usize) -> TextureId {
+        self.textures[index]
+    }
+
+    pub fn set_texture(&mut self, index: usize, texture: TextureId) {
+        self.textures[index] = texture;
+    }
+
+    // drop everything from `len` on, e.g. per-frame particles pushed after the game's sprites
+    pub fn truncate(&mut self, len: usize) {
+        self.sprites.truncate(len);
+        self.layers.truncate(len);
+        self.textures.truncate(len);
+    }
+
+    // Sprites in the order they should be drawn, and the texture batches over them.
+    // Layers draw in order and sprites within a layer in the order they were pushed, so
+    // overlaps come out the same whatever the textures; a new batch starts wherever the
+    // texture changes, so keep a layer's sprites grouped by texture to draw in fewer.
+    pub fn draw_batches(&self) -> (Vec<GPUSprite>, Vec<SpriteBatch>) {
+        let mut order: Vec<usize> = (0..self.sprites.len()).collect();
+        order.sort_by_key(|&i| self.layers[i]);
+        let mut batches: Vec<SpriteBatch> = Vec::new();
+        for (n, &i) in order.iter().enumerate() {
+            match batches.last_mut() {
+                Some(batch) if batch.layer == self.layers[i] && batch.texture == self.textures[i] => {
+                    batch.range.end = n + 1
+                }
+                _ => batches.push(SpriteBatch {
+                    layer: self.layers[i],
+                    texture: self.textures[i],
+                    range: n..n + 1,
+                }),
+            }
+        }
+        (order.into_iter().map(|i| self.sprites[i]).collect(), batches)
+    }
+
+    pub fn draw_order(&self) -> Vec<GPUSprite> {
+        self.draw_batches().0
+    }
+}
+
+impl Deref for SpriteList {
+    type Target = [GPUSprite];
+    fn deref(&self) -> &[GPUSprite] {
+        &self.sprites
+    }
+}
+
+impl DerefMut for SpriteList {
+    fn deref_mut(&mut self) -> &mut [GPUSprite] {
+        &mut self.sprites
+    }
+}
+
+// placement of full-screen images drawn with shader2.wgsl, in -1..1 units:
+// each corner is multiplied by scale then moved by offset. The texture coordinates
+// are scaled and moved the same way, which repeats the image when uv_scale > 1
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub struct GPUOverlay {
+    pub offset: [f32; 2],
+    pub scale: [f32; 2],
+    pub uv_offset: [f32; 2],
+    pub uv_scale: [f32; 2],
+}
+
+impl Default for GPUOverlay {
+    fn default() -> Self {
+        Self {
+            offset: [0.0, 0.0],
+            scale: [1.0, 1.0],
+            uv_offset: [0.0, 0.0],
+            uv_scale: [1.0, 1.0],
+        }
+    }
+}
+
+// full-screen images (title, end screens, background) with shader2.wgsl
+pub fn overlay_pipeline(
+    gpu: &WGPU,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(gpu.config.format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+// Sprites with shader.wgsl: one pipeline for ordinary textures and one with premultiplied
+// blending for textures loaded with `premultiply`. SpriteBuffer picks per texture batch.
+pub struct SpritePipeline {
+    straight: wgpu::RenderPipeline,
+    premultiplied: wgpu::RenderPipeline,
+}
+
+impl SpritePipeline {
+    pub fn for_texture(&self, options: &TextureOptions) -> &wgpu::RenderPipeline {
+        if options.premultiply {
+            &self.premultiplied
+        } else {
+            &self.straight
+        }
+    }
+}
+
+// both sprite pipelines, reading sprites however `sprite_option` says
+pub fn sprite_pipeline(
+    gpu: &WGPU,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    sprite_option: SpriteOption,
+) -> SpritePipeline {
+    let pipeline = |entry_point, blend| sprite_pipeline_with(gpu, layout, shader, sprite_option, entry_point, blend);
+    SpritePipeline {
+        straight: pipeline("fs_main", wgpu::BlendState::ALPHA_BLENDING),
+        premultiplied: pipeline("fs_premultiplied", wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+    }
+}
+
+fn sprite_pipeline_with(
+    gpu: &WGPU,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    sprite_option: SpriteOption,
+    entry_point: &str,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: match sprite_option {
+                SpriteOption::Storage => "vs_storage_main",
+                SpriteOption::Uniform => "vs_uniform_main",
+                SpriteOption::VertexBuffer => "vs_vbuf_main",
+            },
+            buffers: match sprite_option {
+                SpriteOption::VertexBuffer => &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<GPUSprite>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &GPUSprite::ATTRIBUTES,
+                }],
+                _ => &[],
+            },
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            // blend so sprite alpha (tint or texture) fades into what's behind it
+            targets: &[Some(wgpu::ColorTargetState {
+                format: gpu.config.format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+// bytes in one uniform-mode batch; a multiple of every adapter's uniform offset alignment
+const SPRITE_UNIFORM_SIZE: u64 = (SPRITE_UNIFORM_MAX * std::mem::size_of::<GPUSprite>()) as u64;
+
+// The GPU side of a sprite list for whichever SpriteOption the adapter uses. The buffer
+// grows when more sprites are uploaded than fit; in uniform mode each batch starts at an
+// aligned offset and is drawn SPRITE_UNIFORM_MAX at a time, bound at its own dynamic offset.
+pub struct SpriteBuffer {
+    option: SpriteOption,
+    // sprites per uniform offset alignment step, so batches can start on one
+    uniform_align: usize,
+    // group 0 of the sprite pipeline: the camera, plus the sprites unless they're a vertex buffer
+    pub layout: wgpu::BindGroupLayout,
+    buffer: Option<(wgpu::Buffer, wgpu::BindGroup)>,
+    capacity: usize,
+    // sprites in the last upload
+    len: usize,
+    batches: Vec<SpriteBatch>,
+}
+
+impl SpriteBuffer {
+    pub fn new(gpu: &WGPU) -> Self {
+        let option = gpu.sprite_option;
+        let sprite_entry = |ty, has_dynamic_offset, min_binding_size| wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset,
+                min_binding_size,
+            },
+            count: None,
+        };
+        let entries = match option {
+            SpriteOption::Storage => vec![
+                CAMERALAYOUT,
+                sprite_entry(wgpu::BufferBindingType::Storage { read_only: true }, false, None),
+            ],
+            SpriteOption::Uniform => vec![
+                CAMERALAYOUT,
+                sprite_entry(
+                    wgpu::BufferBindingType::Uniform,
+                    true,
+                    wgpu::BufferSize::new(SPRITE_UNIFORM_SIZE),
+                ),
+            ],
+            SpriteOption::VertexBuffer => vec![CAMERALAYOUT],
+        };
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
+        let align = gpu.device.limits().min_uniform_buffer_offset_alignment as usize;
+        Self {
+            option,
+            uniform_align: align.div_ceil(std::mem::size_of::<GPUSprite>()).max(1),
+            layout,
+            buffer: None,
+            capacity: 0,
+            len: 0,
+            batches: Vec::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // uniform mode binds whole batches, so its buffer is always a multiple of one
+    fn round_capacity(&self, count: usize) -> usize {
+        let count = count.max(1);
+        match self.option {
+            SpriteOption::Uniform => count.div_ceil(SPRITE_UNIFORM_MAX) * SPRITE_UNIFORM_MAX,
+            _ => count,
+        }
+    }
+
+    fn create(&mut self, gpu: &WGPU, camera: &wgpu::Buffer, capacity: usize) {
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sprites"),
+            size: (capacity * std::mem::size_of::<GPUSprite>()) as u64,
+            usage: match self.option {
+                SpriteOption::Storage => wgpu::BufferUsages::STORAGE,
+                SpriteOption::Uniform => wgpu::BufferUsages::UNIFORM,
+                SpriteOption::VertexBuffer => wgpu::BufferUsages::VERTEX,
+            } | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_entry = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: camera.as_entire_binding(),
+        };
+        let sprite_resource = match self.option {
+            SpriteOption::Storage => Some(buffer.as_entire_binding()),
+            SpriteOption::Uniform => Some(wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(SPRITE_UNIFORM_SIZE),
+            })),
+            SpriteOption::VertexBuffer => None,
+        };
+        let bind_group = match sprite_resource {
+            Some(resource) => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[camera_entry, wgpu::BindGroupEntry { binding: 1, resource }],
+            }),
+            None => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[camera_entry],
+            }),
+        };
+        self.buffer = Some((buffer, bind_group));
+        self.capacity = capacity;
+    }
+
+    // Copy the list to the GPU in draw order, growing the buffer if it doesn't fit.
+    // `camera` is the GPUCamera uniform bound next to the sprites.
+    pub fn upload(&mut self, gpu: &WGPU, camera: &wgpu::Buffer, sprites: &SpriteList) {
+        let len = sprites.len();
+        let (mut sprites, mut batches) = sprites.draw_batches();
+        // uniform mode binds a whole SPRITE_UNIFORM_MAX window at each batch's start
+        let mut needed = sprites.len();
+        if self.option == SpriteOption::Uniform {
+            (sprites, batches) = align_batches(&sprites, batches, self.uniform_align);
+            needed = sprites.len() + SPRITE_UNIFORM_MAX;
+        }
+        if self.buffer.is_none() || needed > self.capacity {
+            // double so a slowly growing list doesn't reallocate every frame
+            let capacity = self.round_capacity(needed.max(self.capacity * 2));
+            self.create(gpu, camera, capacity);
+        }
+        if let Some((buffer, _)) = &self.buffer {
+            gpu.queue.write_buffer(buffer, 0, bytemuck::cast_slice(&sprites));
+        }
+        self.len = len;
+        self.batches = batches;
+    }
+
+    // Draw everything from the last upload, one batch per texture run, each with the
+    // pipeline its texture needs.
+    pub fn draw<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a SpritePipeline,
+        textures: &'a TextureRegistry,
+    ) {
+        self.draw_layers(rpass, pipeline, textures, ..);
+    }
+
+    // Like draw, but only the layers in `layers`, e.g. `..Layer::Hud` then `Layer::Hud..`
+    // with something drawn over the world in between.
+    pub fn draw_layers<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a SpritePipeline,
+        textures: &'a TextureRegistry,
+        layers: impl RangeBounds<Layer>,
+    ) {
+        let mut current = None;
+        for batch in self.batches.iter().filter(|batch| layers.contains(&batch.layer)) {
+            let options = &textures.get(batch.texture).options;
+            if current != Some(options.premultiply) {
+                rpass.set_pipeline(pipeline.for_texture(options));
+                current = Some(options.premultiply);
+            }
+            rpass.set_bind_group(1, textures.bind_group(batch.texture), &[]);
+            self.draw_range(rpass, batch.range.clone());
+        }
+    }
+
+    fn draw_range<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, range: Range<usize>) {
+        let Some((buffer, bind_group)) = &self.buffer else {
+            return;
+        };
+        let sprite_size = std::mem::size_of::<GPUSprite>();
+        match self.option {
+            SpriteOption::Storage => {
+                rpass.set_bind_group(0, bind_group, &[]);
+                rpass.draw(0..6, range.start as u32..range.end as u32);
+            }
+            SpriteOption::VertexBuffer => {
+                // offset the buffer rather than the instances; WebGL2 has no base instance
+                let bytes = (range.start * sprite_size) as u64..(range.end * sprite_size) as u64;
+                rpass.set_vertex_buffer(0, buffer.slice(bytes));
+                rpass.set_bind_group(0, bind_group, &[]);
+                rpass.draw(0..6, 0..range.len() as u32);
+            }
+            SpriteOption::Uniform => {
+                // bind right at the batch (upload aligned it) and draw from instance 0 like
+                // VertexBuffer, SPRITE_UNIFORM_MAX at a time
+                let mut start = range.start;
+                while start < range.end {
+                    let end = range.end.min(start + SPRITE_UNIFORM_MAX);
+                    rpass.set_bind_group(0, bind_group, &[(start * sprite_size) as u32]);
+                    rpass.draw(0..6, 0..(end - start) as u32);
+                    start = end;
+                }
+            }
+        }
+    }
+}
+
+// Copy the batches so each starts on a multiple of `align` sprites, padding the gaps with
+// zero-sized sprites that are never drawn.
+fn align_batches(sprites: &[GPUSprite], batches: Vec<SpriteBatch>, align: usize) -> (Vec<GPUSprite>, Vec<SpriteBatch>) {
+    let mut aligned = Vec::with_capacity(sprites.len() + batches.len() * align);
+    let batches = batches
+        .into_iter()
+        .map(|batch| {
+            aligned.resize(aligned.len().next_multiple_of(align), GPUSprite::zeroed());
+            let start = aligned.len();
+            aligned.extend_from_slice(&sprites[batch.range.clone()]);
+            SpriteBatch {
+                range: start..aligned.len(),
+                ..batch
+            }
+        })
+        .collect();
+    (aligned, batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_batches_start_aligned() {
+        let mut list = SpriteList::new();
+        for i in 0..3 {
+            let mut sprite = GPUSprite::default();
+            sprite.screen_region[0] = i as f32;
+            list.push(Layer::Floor, sprite);
+        }
+        let mut hud = GPUSprite::default();
+        hud.screen_region[0] = 9.0;
+        list.push(Layer::Hud, hud);
+        let (sprites, batches) = list.draw_batches();
+        let (aligned, batches) = align_batches(&sprites, batches, 4);
+        assert_eq!(batches[0].range, 0..3);
+        assert_eq!(batches[1].range, 4..5);
+        assert_eq!(aligned.len(), 5);
+        assert_eq!(aligned[4].screen_region[0], 9.0);
+        // padding has no size, so it never shows up
+        assert_eq!(aligned[3].screen_region, [0.0; 4]);
+    }
+
+    #[test]
+    fn textures_on_one_layer_keep_their_order() {
+        let mut list = SpriteList::new();
+        let textures = [TextureId(0), TextureId(1), TextureId(0), TextureId(0)];
+        for (i, &texture) in textures.iter().enumerate() {
+            let mut sprite = GPUSprite::default();
+            sprite.screen_region[0] = i as f32;
+            list.push_textured(Layer::Floor, texture, sprite);
+        }
+        // pushed last but on a lower layer
+        list.push_textured(Layer::Background, TextureId(1), GPUSprite::default());
+        let (sprites, batches) = list.draw_batches();
+        let order: Vec<f32> = sprites.iter().map(|s| s.screen_region[0]).collect();
+        assert_eq!(order, vec![0.0, 0.0, 1.0, 2.0, 3.0]);
+        let runs: Vec<(TextureId, std::ops::Range<usize>)> =
+            batches.iter().map(|b| (b.texture, b.range.clone())).collect();
+        assert_eq!(
+            runs,
+            vec![
+                (TextureId(1), 0..1),
+                (TextureId(0), 1..2),
+                (TextureId(1), 2..3),
+                (TextureId(0), 3..5),
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::error::{Error, Result};
+use crate::gpu::{decode_image, WGPU};
+
+// A handle into a TextureRegistry. The first texture registered is the default one,
+// which is what sprites pushed without a texture are drawn with.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct TextureId(pub(crate) usize);
+
+// How a texture is uploaded and sampled. The default is smooth (linear) and clamped;
+// `pixel_art()` keeps hard pixel edges when sprites are scaled up.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureOptions {
+    pub filter: wgpu::FilterMode,
+    // for both u and v; Repeat for tiled floors and backgrounds
+    pub address_mode: wgpu::AddressMode,
+    // build the full mip chain on load, for textures drawn smaller than they are
+    pub mipmaps: bool,
+    // multiply color by alpha on load, e.g. for textures that are filtered or scaled a lot;
+    // SpriteBuffer draws these with premultiplied blending
+    pub premultiply: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            filter: wgpu::FilterMode::Linear,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            mipmaps: false,
+            premultiply: false,
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn pixel_art() -> Self {
+        Self {
+            filter: wgpu::FilterMode::Nearest,
+            ..Self::default()
+        }
+    }
+
+    pub fn repeat(self) -> Self {
+        Self {
+            address_mode: wgpu::AddressMode::Repeat,
+            ..self
+        }
+    }
+
+    pub fn sampler_descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: if self.mipmaps { self.filter } else { wgpu::FilterMode::Nearest },
+            ..Default::default()
+        }
+    }
+}
+
+// a loaded texture with everything needed to draw with it, made once up front
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    // texture at binding 0 and sampler at binding 1, laid out by TextureRegistry::layout
+    pub bind_group: wgpu::BindGroup,
+    pub size: [u32; 2],
+    pub options: TextureOptions,
+}
+
+// Every texture a game draws with, sprite sheets and full-screen images alike.
+// Loading the same path with the same options twice hands back the same id.
+pub struct TextureRegistry {
+    // the texture + sampler bind group layout both shaders use
+    pub layout: wgpu::BindGroupLayout,
+    textures: Vec<Texture>,
+    paths: HashMap<(PathBuf, TextureOptions), TextureId>,
+}
+
+impl TextureRegistry {
+    pub fn new(gpu: &WGPU) -> Self {
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                // The texture binding
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // The sampler binding
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        Self {
+            layout,
+            textures: Vec::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    pub async fn load(&mut self, gpu: &WGPU, path: &Path) -> Result<TextureId> {
+        self.load_with(gpu, path, TextureOptions::default()).await
+    }
+
+    pub async fn load_with(
+        &mut self,
+        gpu: &WGPU,
+        path: &Path,
+        options: TextureOptions,
+    ) -> Result<TextureId> {
+        let key = (path.to_path_buf(), options);
+        if let Some(&id) = self.paths.get(&key) {
+            return Ok(id);
+        }
+        let img = gpu.load_image(path).await?;
+        let texture = gpu.texture_from_image(&img, path.to_str(), &options);
+        let id = self.insert_with(gpu, texture, options);
+        self.paths.insert(key, id);
+        Ok(id)
+    }
+
+    // An id for `path` before its image is ready: a transparent 1x1 stand-in registered
+    // under the path, to be swapped for the real texture with replace(). True if the id
+    // is new, false if `path` was already loaded or reserved with these options.
+    pub fn reserve(&mut self, gpu: &WGPU, path: &Path, options: TextureOptions) -> (TextureId, bool) {
+        let key = (path.to_path_buf(), options);
+        if let Some(&id) = self.paths.get(&key) {
+            return (id, false);
+        }
+        let blank = image::RgbaImage::new(1, 1);
+        let texture = gpu.texture_from_image(&blank, path.to_str(), &TextureOptions::default());
+        let id = self.insert_with(gpu, texture, options);
+        self.paths.insert(key, id);
+        (id, true)
+    }
+
+    // register a texture made some other way (render targets, generated images)
+    pub fn insert(&mut self, gpu: &WGPU, texture: wgpu::Texture) -> TextureId {
+        self.insert_with(gpu, texture, TextureOptions::default())
+    }
+
+    // `options` only picks the sampler here; the texture is used as it is
+    pub fn insert_with(&mut self, gpu: &WGPU, texture: wgpu::Texture, options: TextureOptions) -> TextureId {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = gpu.device.create_sampler(&options.sampler_descriptor());
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        let size = [texture.width(), texture.height()];
+        self.textures.push(Texture {
+            texture,
+            view,
+            sampler,
+            bind_group,
+            size,
+            options,
+        });
+        TextureId(self.textures.len() - 1)
+    }
+
+    // swap the texture behind `id` for another, keeping its sampler; anything drawing by
+    // id picks up the new one, so sprites don't need to know it changed
+    pub fn replace(&mut self, gpu: &WGPU, id: TextureId, texture: wgpu::Texture) {
+        let entry = &mut self.textures[id.0];
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        entry.bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&entry.sampler),
+                },
+            ],
+        });
+        entry.size = [texture.width(), texture.height()];
+        entry.texture = texture;
+        entry.view = view;
+    }
+
+    // Free the texture behind `id`, leaving a transparent 1x1 in its place. The id stays
+    // valid (ids are never removed) and can be given a texture again with replace().
+    pub fn release(&mut self, gpu: &WGPU, id: TextureId) {
+        let blank = image::RgbaImage::new(1, 1);
+        let texture = gpu.texture_from_image(&blank, Some("released"), &TextureOptions::default());
+        self.replace(gpu, id, texture);
+    }
+
+    // Read `path` from disk again and update every texture loaded from it. Same-sized
+    // images are written into the existing texture; a new size replaces it under the same
+    // id. Returns the ids that changed, empty if nothing was loaded from `path`.
+    pub fn reload(&mut self, gpu: &WGPU, path: &Path) -> Result<Vec<TextureId>> {
+        let ids: Vec<(TextureId, TextureOptions)> = self
+            .paths
+            .iter()
+            .filter(|((p, _), _)| p == path)
+            .map(|(&(_, options), &id)| (id, options))
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let bytes = crate::vfs::read(path).map_err(Error::io(path))?;
+        let img = decode_image(path, &bytes)?;
+        for &(id, options) in &ids {
+            if self.size(id) == [img.width(), img.height()] {
+                gpu.write_image(&self.textures[id.0].texture, &img, &options);
+            } else {
+                let texture = gpu.texture_from_image(&img, path.to_str(), &options);
+                self.replace(gpu, id, texture);
+            }
+        }
+        log::info!("Reloaded {}", path.display());
+        Ok(ids.into_iter().map(|(id, _)| id).collect())
+    }
+
+    pub fn get(&self, id: TextureId) -> &Texture {
+        &self.textures[id.0]
+    }
+
+    pub fn bind_group(&self, id: TextureId) -> &wgpu::BindGroup {
+        &self.textures[id.0].bind_group
+    }
+
+    pub fn size(&self, id: TextureId) -> [u32; 2] {
+        self.textures[id.0].size
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+}
@@ -0,0 +1,338 @@
+use std::borrow::Cow;
+use bytemuck::{Pod, Zeroable};
+use crate::gpu::WGPU;
+use crate::tween::Ease;
+
+// how one scene gives way to the next
+#[derive(Clone, Copy, Debug)]
+pub enum TransitionKind {
+    // switch straight away
+    Cut,
+    // blend the two scenes
+    Crossfade,
+    // out to `color`, then in from it
+    Fade { color: [f32; 3] },
+    // an edge sweeps across in direction `angle` (radians, 0 moving right and PI/2 up),
+    // softness being the width of the edge as a fraction of the screen
+    Wipe { angle: f32, softness: f32 },
+    // a circle closes on `center` (fractions of the screen, [0, 0] bottom-left) leaving
+    // `color`, then opens again on the new scene
+    Iris { center: [f32; 2], color: [f32; 3] },
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct GPUParams {
+    a: [f32; 4],
+    b: [f32; 4],
+    color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Running {
+    kind: TransitionKind,
+    duration: f32,
+    elapsed: f32,
+}
+
+struct Target {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// Transitions between scenes. The scene is always drawn into scene_view(), one of two
+// offscreen targets, and apply() copies it to the frame. start() swaps the targets, so
+// the last frame of the outgoing scene stays in one while the incoming scene draws into
+// the other, and apply() blends the two until the transition is over.
+pub struct Transition {
+    // what start() uses
+    pub kind: TransitionKind,
+    pub duration: f32,
+    pub ease: Ease,
+    running: Option<Running>,
+    format: wgpu::TextureFormat,
+    size: [u32; 2],
+    source_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    targets: Vec<Target>,
+    // the target the scene is being drawn into; the other holds the outgoing scene
+    current: usize,
+    params: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    crossfade: wgpu::RenderPipeline,
+    fade: wgpu::RenderPipeline,
+    wipe: wgpu::RenderPipeline,
+    iris: wgpu::RenderPipeline,
+}
+
+impl Transition {
+    pub fn new(gpu: &WGPU) -> Self {
+        let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("transition"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("transition.wgsl"))),
+        });
+        let source_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let params_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&source_layout, &source_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let format = gpu.config.format;
+        let pipeline = |entry_point: &str| {
+            gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let crossfade = pipeline("fs_crossfade");
+        let fade = pipeline("fs_fade");
+        let wipe = pipeline("fs_wipe");
+        let iris = pipeline("fs_iris");
+        let sampler = gpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let params = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("transition params"),
+            size: std::mem::size_of::<GPUParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            }],
+        });
+        Self {
+            kind: TransitionKind::Fade { color: [0.0, 0.0, 0.0] },
+            duration: 0.6,
+            ease: Ease::QuadInOut,
+            running: None,
+            format,
+            size: [0, 0],
+            source_layout,
+            sampler,
+            targets: Vec::new(),
+            current: 0,
+            params,
+            params_bind_group,
+            crossfade,
+            fade,
+            wipe,
+            iris,
+        }
+    }
+
+    // begin the default transition; call when the scene changes, before drawing the new one
+    pub fn start(&mut self) {
+        self.start_with(self.kind, self.duration);
+    }
+
+    pub fn start_with(&mut self, kind: TransitionKind, duration: f32) {
+        if matches!(kind, TransitionKind::Cut) || duration <= 0.0 {
+            self.running = None;
+            return;
+        }
+        // what was drawn last frame becomes the outgoing scene
+        self.current = 1 - self.current;
+        self.running = Some(Running {
+            kind,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    // eased progress through the running transition, 1 when there isn't one
+    pub fn progress(&self) -> f32 {
+        self.running
+            .map_or(1.0, |running| self.ease.apply(running.elapsed / running.duration))
+    }
+
+    // advance the transition and make sure the targets match the window; call before drawing
+    pub fn update(&mut self, gpu: &WGPU, dt: f32) {
+        if let Some(running) = &mut self.running {
+            running.elapsed += dt;
+            if running.elapsed >= running.duration {
+                self.running = None;
+            }
+        }
+        let size = [gpu.config.width.max(1), gpu.config.height.max(1)];
+        if self.size != size || self.targets.is_empty() {
+            self.targets = (0..2).map(|_| self.create_target(gpu, size)).collect();
+            self.size = size;
+        }
+    }
+
+    fn create_target(&self, gpu: &WGPU, size: [u32; 2]) -> Target {
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("transition target"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            // COPY_SRC so Capture can read the scene back
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        Target { texture, view, bind_group }
+    }
+
+    // Where the scene should be drawn this frame. Chains with PostFx: draw into
+    // postfx.scene_view(transition.scene_view(&frame)) and apply the post effects into
+    // transition.scene_view(&frame) before transition.apply().
+    pub fn scene_view<'a>(&'a self, frame: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        match self.targets.get(self.current) {
+            Some(target) => &target.view,
+            None => frame,
+        }
+    }
+
+    // the texture behind scene_view(), for reading the frame back (without the transition blend)
+    pub fn scene_texture(&self) -> Option<&wgpu::Texture> {
+        self.targets.get(self.current).map(|target| &target.texture)
+    }
+
+    fn params(&self) -> GPUParams {
+        let t = self.progress();
+        let color = |c: [f32; 3]| [c[0], c[1], c[2], 1.0];
+        match self.running.map(|running| running.kind) {
+            Some(TransitionKind::Fade { color: c }) => GPUParams {
+                a: [t, 0.0, 0.0, 0.0],
+                b: [0.0; 4],
+                color: color(c),
+            },
+            Some(TransitionKind::Wipe { angle, softness }) => GPUParams {
+                a: [t, softness.max(0.0001), 0.0, 0.0],
+                // tex_coords run down the screen
+                b: [angle.cos(), -angle.sin(), 0.0, 0.0],
+                color: [0.0; 4],
+            },
+            Some(TransitionKind::Iris { center, color: c }) => {
+                let aspect = self.size[0] as f32 / self.size[1] as f32;
+                let center = [center[0], 1.0 - center[1]];
+                // far enough to uncover the furthest corner
+                let dx = center[0].max(1.0 - center[0]) * aspect;
+                let dy = center[1].max(1.0 - center[1]);
+                GPUParams {
+                    a: [t, (dx * dx + dy * dy).sqrt() + 0.01, aspect, 0.0],
+                    b: [center[0], center[1], 0.0, 0.0],
+                    color: color(c),
+                }
+            }
+            // crossfade, or just the incoming scene when nothing's running
+            _ => GPUParams {
+                a: [t, 0.0, 0.0, 0.0],
+                b: [0.0; 4],
+                color: [0.0; 4],
+            },
+        }
+    }
+
+    // draw the scene, blended with the outgoing one while a transition runs, into `frame`
+    pub fn apply(&self, gpu: &WGPU, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView) {
+        if self.targets.len() < 2 {
+            return;
+        }
+        gpu.queue.write_buffer(&self.params, 0, bytemuck::bytes_of(&self.params()));
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("transition"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: frame,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(match self.running.map(|running| running.kind) {
+            Some(TransitionKind::Fade { .. }) => &self.fade,
+            Some(TransitionKind::Wipe { .. }) => &self.wipe,
+            Some(TransitionKind::Iris { .. }) => &self.iris,
+            _ => &self.crossfade,
+        });
+        rpass.set_bind_group(0, &self.targets[1 - self.current].bind_group, &[]);
+        rpass.set_bind_group(1, &self.targets[self.current].bind_group, &[]);
+        rpass.set_bind_group(2, &self.params_bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
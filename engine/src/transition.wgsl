@@ -0,0 +1,87 @@
+// Blends the outgoing scene (group 0) into the incoming one (group 1). Transition in
+// transition.rs picks the fragment entry point for each kind.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// one triangle that covers the screen, like postfx.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return VertexOutput(
+        vec4<f32>(uv.x * 2. - 1., 1. - uv.y * 2., 0., 1.),
+        uv
+    );
+}
+
+@group(0) @binding(0)
+var t_from: texture_2d<f32>;
+@group(0) @binding(1)
+var s_from: sampler;
+@group(1) @binding(0)
+var t_to: texture_2d<f32>;
+@group(1) @binding(1)
+var s_to: sampler;
+
+// a.x is always the eased progress 0..1; the rest depends on the kind, see Kind in transition.rs
+struct Params {
+    a: vec4<f32>,
+    b: vec4<f32>,
+    color: vec4<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> params: Params;
+
+@fragment
+fn fs_crossfade(in: VertexOutput) -> @location(0) vec4<f32> {
+    let outgoing = textureSample(t_from, s_from, in.tex_coords);
+    let incoming = textureSample(t_to, s_to, in.tex_coords);
+    return mix(outgoing, incoming, params.a.x);
+}
+
+// out to color over the first half, in from it over the second
+@fragment
+fn fs_fade(in: VertexOutput) -> @location(0) vec4<f32> {
+    let outgoing = textureSample(t_from, s_from, in.tex_coords);
+    let incoming = textureSample(t_to, s_to, in.tex_coords);
+    let t = params.a.x;
+    if t < 0.5 {
+        return mix(outgoing, params.color, t * 2.);
+    }
+    return mix(params.color, incoming, t * 2. - 1.);
+}
+
+// b.xy the direction the edge moves (y down, like tex_coords), a.y the edge's softness
+@fragment
+fn fs_wipe(in: VertexOutput) -> @location(0) vec4<f32> {
+    let outgoing = textureSample(t_from, s_from, in.tex_coords);
+    let incoming = textureSample(t_to, s_to, in.tex_coords);
+    let dir = params.b.xy;
+    // 0 where the wipe starts, 1 where it ends
+    let s = dot(in.tex_coords - vec2<f32>(0.5), dir) / (abs(dir.x) + abs(dir.y)) + 0.5;
+    let soft = params.a.y;
+    let edge = params.a.x * (1. + soft);
+    let reveal = 1. - smoothstep(edge - soft, edge, s);
+    return mix(outgoing, incoming, reveal);
+}
+
+// A circle closes on the outgoing scene over the first half and opens on the incoming
+// one over the second. b.xy the circle's center, a.y its largest radius, a.z the screen's
+// aspect ratio; color fills outside the circle.
+@fragment
+fn fs_iris(in: VertexOutput) -> @location(0) vec4<f32> {
+    let outgoing = textureSample(t_from, s_from, in.tex_coords);
+    let incoming = textureSample(t_to, s_to, in.tex_coords);
+    let t = params.a.x;
+    let d = length((in.tex_coords - params.b.xy) * vec2<f32>(params.a.z, 1.));
+    let radius = params.a.y * abs(1. - t * 2.);
+    let inside = 1. - smoothstep(radius - 0.005, radius + 0.005, d);
+    var scene = incoming;
+    if t < 0.5 {
+        scene = outgoing;
+    }
+    return mix(params.color, scene, inside);
+}
@@ -0,0 +1,329 @@
+use crate::sprite::GPUSprite;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    BounceIn,
+    BounceOut,
+}
+
+impl Ease {
+    // map progress `t` in 0..1 onto eased progress (elastic can overshoot past 0..1)
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Ease::ElasticIn => 1.0 - Ease::ElasticOut.apply(1.0 - t),
+            Ease::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    let c4 = (2.0 * std::f32::consts::PI) / 3.0;
+                    2.0_f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+                }
+            }
+            Ease::BounceIn => 1.0 - Ease::BounceOut.apply(1.0 - t),
+            Ease::BounceOut => {
+                let n1 = 7.5625;
+                let d1 = 2.75;
+                if t < 1.0 / d1 {
+                    n1 * t * t
+                } else if t < 2.0 / d1 {
+                    let t = t - 1.5 / d1;
+                    n1 * t * t + 0.75
+                } else if t < 2.5 / d1 {
+                    let t = t - 2.25 / d1;
+                    n1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d1;
+                    n1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+// one f32 going from `from` to `to` over `duration` seconds
+pub struct Tween {
+    pub from: f32,
+    pub to: f32,
+    pub duration: f32,
+    pub ease: Ease,
+    elapsed: f32,
+    completed: bool,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32, ease: Ease) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            ease,
+            elapsed: 0.0,
+            completed: false,
+            on_complete: None,
+        }
+    }
+
+    // run `f` once, on the update where the tween reaches `to`
+    pub fn on_complete(mut self, f: impl FnMut() + 'static) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn value(&self) -> f32 {
+        if self.is_done() {
+            return self.to;
+        }
+        let t = self.ease.apply(self.elapsed / self.duration);
+        self.from + (self.to - self.from) * t
+    }
+
+    // advance by `dt` seconds and return the new value
+    pub fn update(&mut self, dt: f32) -> f32 {
+        if !self.completed {
+            self.elapsed += dt;
+            if self.is_done() {
+                self.completed = true;
+                if let Some(f) = self.on_complete.as_mut() {
+                    f();
+                }
+            }
+        }
+        self.value()
+    }
+}
+
+// which number inside a GPUSprite a tween drives
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpriteField {
+    ScreenX,
+    ScreenY,
+    ScreenW,
+    ScreenH,
+    SheetX,
+    SheetY,
+    SheetW,
+    SheetH,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Rotation,
+}
+
+impl SpriteField {
+    pub fn get_mut(self, sprite: &mut GPUSprite) -> &mut f32 {
+        match self {
+            SpriteField::ScreenX => &mut sprite.screen_region[0],
+            SpriteField::ScreenY => &mut sprite.screen_region[1],
+            SpriteField::ScreenW => &mut sprite.screen_region[2],
+            SpriteField::ScreenH => &mut sprite.screen_region[3],
+            SpriteField::SheetX => &mut sprite.sheet_region[0],
+            SpriteField::SheetY => &mut sprite.sheet_region[1],
+            SpriteField::SheetW => &mut sprite.sheet_region[2],
+            SpriteField::SheetH => &mut sprite.sheet_region[3],
+            SpriteField::Red => &mut sprite.color[0],
+            SpriteField::Green => &mut sprite.color[1],
+            SpriteField::Blue => &mut sprite.color[2],
+            SpriteField::Alpha => &mut sprite.color[3],
+            SpriteField::Rotation => &mut sprite.transform[0],
+        }
+    }
+}
+
+type SpriteCallback = Box<dyn FnMut(&mut [GPUSprite])>;
+
+struct SpriteTween {
+    sprite: usize,
+    field: SpriteField,
+    tween: Tween,
+    on_complete: Option<SpriteCallback>,
+}
+
+// drives tweens on fields of the game's sprite list, addressed by index like everything else
+#[derive(Default)]
+pub struct Tweener {
+    tweens: Vec<SpriteTween>,
+}
+
+impl Tweener {
+    // start tweening `sprites[sprite]`'s field, replacing any tween already running on that field
+    pub fn add(&mut self, sprite: usize, field: SpriteField, tween: Tween) {
+        self.tweens.retain(|t| !(t.sprite == sprite && t.field == field));
+        self.tweens.push(SpriteTween {
+            sprite,
+            field,
+            tween,
+            on_complete: None,
+        });
+    }
+
+    // same as `add`, but `f` gets the whole sprite list once the tween finishes
+    pub fn add_then(
+        &mut self,
+        sprite: usize,
+        field: SpriteField,
+        tween: Tween,
+        f: impl FnMut(&mut [GPUSprite]) + 'static,
+    ) {
+        self.add(sprite, field, tween);
+        if let Some(t) = self.tweens.last_mut() {
+            t.on_complete = Some(Box::new(f));
+        }
+    }
+
+    // drop every tween on `sprite`, leaving its fields wherever they are now
+    pub fn cancel(&mut self, sprite: usize) {
+        self.tweens.retain(|t| t.sprite != sprite);
+    }
+
+    pub fn clear(&mut self) {
+        self.tweens.clear();
+    }
+
+    pub fn is_tweening(&self, sprite: usize) -> bool {
+        self.tweens.iter().any(|t| t.sprite == sprite)
+    }
+
+    pub fn update(&mut self, dt: f32, sprites: &mut [GPUSprite]) {
+        for t in self.tweens.iter_mut() {
+            let value = t.tween.update(dt);
+            if let Some(sprite) = sprites.get_mut(t.sprite) {
+                *t.field.get_mut(sprite) = value;
+            }
+        }
+        // callbacks run after every field is written so they see this frame's values
+        let mut i = 0;
+        while i < self.tweens.len() {
+            if self.tweens[i].tween.is_done() {
+                let mut done = self.tweens.remove(i);
+                if let Some(f) = done.on_complete.as_mut() {
+                    f(sprites);
+                }
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const EASES: [Ease; 11] = [
+        Ease::Linear,
+        Ease::QuadIn,
+        Ease::QuadOut,
+        Ease::QuadInOut,
+        Ease::CubicIn,
+        Ease::CubicOut,
+        Ease::CubicInOut,
+        Ease::ElasticIn,
+        Ease::ElasticOut,
+        Ease::BounceIn,
+        Ease::BounceOut,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn eases_start_at_0_and_end_at_1() {
+        for ease in EASES {
+            assert!(close(ease.apply(0.0), 0.0), "{:?}", ease);
+            assert!(close(ease.apply(1.0), 1.0), "{:?}", ease);
+        }
+    }
+
+    #[test]
+    fn eases_clamp_progress() {
+        for ease in EASES {
+            assert!(close(ease.apply(-1.0), 0.0), "{:?}", ease);
+            assert!(close(ease.apply(2.0), 1.0), "{:?}", ease);
+        }
+    }
+
+    #[test]
+    fn in_out_eases_meet_halfway() {
+        assert!(close(Ease::QuadInOut.apply(0.5), 0.5));
+        assert!(close(Ease::CubicInOut.apply(0.5), 0.5));
+        assert!(close(Ease::QuadIn.apply(0.5), 0.25));
+        assert!(close(Ease::QuadOut.apply(0.5), 0.75));
+    }
+
+    #[test]
+    fn tween_lands_on_to_and_completes_once() {
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        let mut tween = Tween::new(10.0, 20.0, 1.0, Ease::Linear).on_complete(move || counter.set(counter.get() + 1));
+        assert!(close(tween.value(), 10.0));
+        assert!(close(tween.update(0.5), 15.0));
+        assert!(!tween.is_done());
+        // overshooting the duration still lands exactly on `to`
+        assert_eq!(tween.update(0.75), 20.0);
+        assert!(tween.is_done());
+        assert_eq!(tween.update(1.0), 20.0);
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn zero_duration_tween_is_done_at_once() {
+        let mut tween = Tween::new(0.0, 5.0, 0.0, Ease::QuadIn);
+        assert_eq!(tween.value(), 5.0);
+        assert_eq!(tween.update(0.0), 5.0);
+    }
+
+    #[test]
+    fn tweener_writes_fields_and_replaces_tweens() {
+        let mut sprites: Vec<GPUSprite> = vec![bytemuck::Zeroable::zeroed(); 2];
+        let mut tweener = Tweener::default();
+        tweener.add(1, SpriteField::Alpha, Tween::new(0.0, 1.0, 1.0, Ease::Linear));
+        tweener.update(0.5, &mut sprites);
+        assert!(close(sprites[1].color[3], 0.5));
+        // a second tween on the same field takes over from the first
+        tweener.add_then(1, SpriteField::Alpha, Tween::new(1.0, 0.0, 1.0, Ease::Linear), |sprites| {
+            sprites[0].screen_region[0] = 7.0;
+        });
+        tweener.update(1.0, &mut sprites);
+        assert_eq!(sprites[1].color[3], 0.0);
+        assert_eq!(sprites[0].screen_region[0], 7.0);
+        assert!(!tweener.is_tweening(1));
+    }
+}
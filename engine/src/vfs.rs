@@ -0,0 +1,362 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+// the first bytes of an archive written by pack()
+const MAGIC: &[u8; 4] = b"U3PK";
+// what's looked for next to the executable and mounted automatically
+pub const ARCHIVE_NAME: &str = "content.pak";
+
+// Somewhere game files can come from. Paths are always relative and use '/', e.g.
+// "content/sprites-overlay.png", whatever the source.
+pub enum Source {
+    // a directory on disk the relative paths are resolved against
+    Dir(PathBuf),
+    // files baked into the executable with embed_files!
+    Embedded(&'static [(&'static str, &'static [u8])]),
+    // files packed into one file with pack()
+    Archive(Archive),
+}
+
+// All of an archive's files in memory, found by name.
+pub struct Archive {
+    data: Vec<u8>,
+    files: HashMap<String, (usize, usize)>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    // Magic, file count, then per file its name length, name, offset and length, then the
+    // data. Nothing in the file is trusted: a damaged one is an error, never a panic or a
+    // huge allocation.
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        let mut at: usize = 0;
+        let mut take = |n: usize| -> io::Result<&[u8]> {
+            let end = at.checked_add(n).ok_or_else(|| invalid("archive is cut short"))?;
+            let bytes = data.get(at..end).ok_or_else(|| invalid("archive is cut short"))?;
+            at = end;
+            Ok(bytes)
+        };
+        if take(4)? != MAGIC {
+            return Err(invalid("not an archive"));
+        }
+        let u32_at = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+        // sizes are u64 in the file but usize may be 32 bits (wasm)
+        let u64_at = |b: &[u8]| {
+            let n = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+            usize::try_from(n).map_err(|_| invalid("file runs past the end of the archive"))
+        };
+        let count = u32_at(take(4)?);
+        // each entry is at least 20 bytes, so a bad count can't reserve more than the file
+        let mut entries = Vec::with_capacity(count.min(data.len() / 20));
+        for _ in 0..count {
+            let len = u32_at(take(4)?);
+            let name = String::from_utf8(take(len)?.to_vec()).map_err(|_| invalid("file name isn't UTF-8"))?;
+            let offset = u64_at(take(8)?)?;
+            let size = u64_at(take(8)?)?;
+            entries.push((name, offset, size));
+        }
+        let start = at;
+        let mut files = HashMap::with_capacity(entries.len());
+        for (name, offset, size) in entries {
+            let end = start.checked_add(offset).and_then(|begin| begin.checked_add(size));
+            if end.is_none_or(|end| end > data.len()) {
+                return Err(invalid("file runs past the end of the archive"));
+            }
+            files.insert(name, (start + offset, size));
+        }
+        Ok(Self { data, files })
+    }
+
+    fn get(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(|&(start, size)| &self.data[start..start + size])
+    }
+}
+
+// Pack every file under `dir` into one archive at `out`, named `prefix`/<path under dir>
+// (e.g. pack("content", "content", "content.pak") keeps "content/..." working). Returns
+// how many files went in.
+pub fn pack(dir: &Path, prefix: &str, out: &Path) -> io::Result<usize> {
+    let mut files = Vec::new();
+    collect(dir, prefix, &mut files)?;
+    files.sort();
+    let mut index = Vec::new();
+    let mut data = Vec::new();
+    index.extend_from_slice(MAGIC);
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    for (name, path) in &files {
+        let bytes = std::fs::read(path)?;
+        index.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        data.extend_from_slice(&bytes);
+    }
+    index.extend_from_slice(&data);
+    std::fs::write(out, index)?;
+    Ok(files.len())
+}
+
+fn collect(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = format!("{}/{}", prefix, path.file_name().unwrap_or_default().to_string_lossy());
+        if path.is_dir() {
+            collect(&path, &name, out)?;
+        } else {
+            out.push((name, path));
+        }
+    }
+    Ok(())
+}
+
+// "./content\\a.png" -> "content/a.png"
+fn normalize(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    path.trim_start_matches("./").to_string()
+}
+
+// The working directory first, so files being edited (and hot reloaded) win, then a
+// content.pak next to the executable if there is one, then the executable's own
+// directory, so the games run from anywhere they're started. Nothing on the web, where
+// files are fetched from the server unless something's mounted.
+fn default_sources() -> Vec<Source> {
+    if cfg!(target_arch = "wasm32") {
+        return Vec::new();
+    }
+    let mut sources = vec![Source::Dir(PathBuf::from("."))];
+    if let Some(dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        match Archive::open(&dir.join(ARCHIVE_NAME)) {
+            Ok(archive) => sources.push(Source::Archive(archive)),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => log::warn!("{}: {}", ARCHIVE_NAME, e),
+            Err(_) => {}
+        }
+        sources.push(Source::Dir(dir));
+    }
+    sources
+}
+
+fn sources() -> &'static RwLock<Vec<Source>> {
+    static SOURCES: OnceLock<RwLock<Vec<Source>>> = OnceLock::new();
+    SOURCES.get_or_init(|| RwLock::new(default_sources()))
+}
+
+// Add a source ahead of the others except the working directory, so its files win over
+// the same paths in a content.pak or the executable's directory but content/ edits during
+// development still show up. Mount before loading anything.
+pub fn mount(source: Source) {
+    insert_mount(&mut sources().write().unwrap_or_else(|e| e.into_inner()), source);
+}
+
+fn insert_mount(sources: &mut Vec<Source>, source: Source) {
+    let cwd = sources
+        .iter()
+        .take_while(|source| matches!(source, Source::Dir(dir) if dir == Path::new(".")))
+        .count();
+    sources.insert(cwd, source);
+}
+
+impl Source {
+    fn read(&self, name: &str) -> Option<io::Result<Cow<'static, [u8]>>> {
+        match self {
+            Source::Dir(dir) => match std::fs::read(dir.join(name)) {
+                Ok(bytes) => Some(Ok(Cow::Owned(bytes))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => Some(Err(e)),
+            },
+            Source::Embedded(files) => files
+                .iter()
+                .find(|(path, _)| *path == name)
+                .map(|&(_, bytes)| Ok(Cow::Borrowed(bytes))),
+            Source::Archive(archive) => archive.get(name).map(|bytes| Ok(Cow::Owned(bytes.to_vec()))),
+        }
+    }
+
+    fn list(&self, dir: &str, out: &mut BTreeSet<String>) {
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        // only files directly inside `dir`
+        let mut add = |name: &str| {
+            if let Some(rest) = name.strip_prefix(&prefix) {
+                if !rest.contains('/') {
+                    out.insert(name.to_string());
+                }
+            }
+        };
+        match self {
+            Source::Dir(root) => {
+                let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+                    return;
+                };
+                for entry in entries.flatten() {
+                    if entry.path().is_file() {
+                        add(&format!("{}{}", prefix, entry.file_name().to_string_lossy()));
+                    }
+                }
+            }
+            Source::Embedded(files) => files.iter().for_each(|(name, _)| add(name)),
+            Source::Archive(archive) => archive.files.keys().for_each(|name| add(name)),
+        }
+    }
+}
+
+// the contents of `path` from the first source that has it; errors leave the path to
+// the caller, as std::fs's do
+pub fn read(path: &Path) -> io::Result<Cow<'static, [u8]>> {
+    let name = normalize(path);
+    let sources = sources().read().unwrap_or_else(|e| e.into_inner());
+    sources
+        .iter()
+        .find_map(|source| source.read(&name))
+        .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::NotFound, "not found")))
+}
+
+// read(), except that on the web anything not mounted is fetched from the server, relative
+// to the page (so content/ should sit next to index.html)
+pub async fn fetch(path: &Path) -> io::Result<Cow<'static, [u8]>> {
+    let local = read(path);
+    #[cfg(target_arch = "wasm32")]
+    if local.is_err() {
+        return http_get(&normalize(path)).await.map(Cow::Owned);
+    }
+    local
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn http_get(url: &str) -> io::Result<Vec<u8>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    let js_error = |e: wasm_bindgen::JsValue| io::Error::new(io::ErrorKind::Other, format!("{}: {:?}", url, e));
+    let window = web_sys::window().ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no window to fetch from"))?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: HTTP {}", url, response.status())));
+    }
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+// every file directly inside `dir` in any source, sorted
+pub fn list(dir: &Path) -> Vec<PathBuf> {
+    let mut names = BTreeSet::new();
+    let dir = normalize(dir);
+    for source in sources().read().unwrap_or_else(|e| e.into_inner()).iter() {
+        source.list(&dir, &mut names);
+    }
+    names.into_iter().map(PathBuf::from).collect()
+}
+
+// Bake files into the executable for vfs::mount(Source::Embedded(...)). `dir` is relative to
+// the calling crate's Cargo.toml and each file is mounted as "`as`/<file>":
+//     embed_files!("../content" as "content": "sprites-overlay.png", "player_hit.ogg")
+#[macro_export]
+macro_rules! embed_files {
+    ($dir:literal as $name:literal: $($file:literal),* $(,)?) => {
+        &[$((
+            concat!($name, "/", $file),
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $dir, "/", $file)) as &[u8],
+        )),*]
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory under the system temp dir holding `files`
+    fn temp_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vfs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, bytes) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, bytes).unwrap();
+        }
+        dir
+    }
+
+    fn packed(name: &str) -> Vec<u8> {
+        let dir = temp_dir(name, &[("a.png", b"first"), ("sub/b.ogg", b"second file"), ("c.txt", b"3")]);
+        let out = dir.with_extension("pak");
+        assert_eq!(pack(&dir, "content", &out).unwrap(), 3);
+        let bytes = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_file(&out);
+        bytes
+    }
+
+    #[test]
+    fn pack_round_trips() {
+        let archive = Archive::from_bytes(packed("round-trip")).unwrap();
+        assert_eq!(archive.get("content/a.png"), Some(&b"first"[..]));
+        assert_eq!(archive.get("content/sub/b.ogg"), Some(&b"second file"[..]));
+        assert_eq!(archive.get("content/c.txt"), Some(&b"3"[..]));
+        assert_eq!(archive.get("content/missing"), None);
+        let mut names = BTreeSet::new();
+        Source::Archive(archive).list("content", &mut names);
+        assert_eq!(names.into_iter().collect::<Vec<_>>(), ["content/a.png", "content/c.txt"]);
+    }
+
+    #[test]
+    fn truncated_archive_is_an_error() {
+        let bytes = packed("truncated");
+        for len in 0..bytes.len() {
+            assert!(Archive::from_bytes(bytes[..len].to_vec()).is_err(), "cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn corrupt_archive_is_an_error() {
+        assert!(Archive::from_bytes(b"PK\x03\x04 not ours".to_vec()).is_err());
+        // a file count far bigger than the file
+        let mut huge_count = MAGIC.to_vec();
+        huge_count.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Archive::from_bytes(huge_count).is_err());
+        // an offset and length that overflow
+        let mut overflow = MAGIC.to_vec();
+        overflow.extend_from_slice(&1u32.to_le_bytes());
+        overflow.extend_from_slice(&1u32.to_le_bytes());
+        overflow.push(b'a');
+        overflow.extend_from_slice(&u64::MAX.to_le_bytes());
+        overflow.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Archive::from_bytes(overflow).is_err());
+        // a name that isn't UTF-8
+        let mut bad_name = MAGIC.to_vec();
+        bad_name.extend_from_slice(&1u32.to_le_bytes());
+        bad_name.extend_from_slice(&1u32.to_le_bytes());
+        bad_name.push(0xff);
+        bad_name.extend_from_slice(&[0; 16]);
+        assert!(Archive::from_bytes(bad_name).is_err());
+    }
+
+    #[test]
+    fn mounts_go_behind_the_working_directory() {
+        let dir = temp_dir("mount", &[("content/same.txt", b"on disk")]);
+        let mut sources = vec![Source::Dir(PathBuf::from(".")), Source::Dir(dir.clone())];
+        static FILES: &[(&str, &[u8])] = &[("content/same.txt", b"embedded")];
+        insert_mount(&mut sources, Source::Embedded(FILES));
+        assert!(matches!(&sources[0], Source::Dir(dir) if dir == Path::new(".")));
+        assert!(matches!(sources[1], Source::Embedded(_)));
+        let found = sources.iter().skip(1).find_map(|source| source.read("content/same.txt"));
+        assert_eq!(&found.unwrap().unwrap()[..], b"embedded");
+        // with nothing else mounted (the web) it simply goes first
+        let mut sources = Vec::new();
+        insert_mount(&mut sources, Source::Embedded(FILES));
+        assert_eq!(sources.len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
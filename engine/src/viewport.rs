@@ -0,0 +1,210 @@
+use crate::input::MousePos;
+use crate::sprite::GPUCamera;
+
+// how the fixed virtual screen is fit into a window of some other size
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScaleMode {
+    // as big as fits while keeping the aspect ratio, with bars on two sides
+    Fit,
+    // cover the whole window keeping the aspect ratio, cropping two sides
+    Fill,
+    // like Fit but only whole-number scales, so pixel art stays crisp
+    IntegerScale,
+    // cover the whole window, ignoring the aspect ratio
+    Stretch,
+}
+
+// Where the virtual screen (e.g. WINDOW_WIDTH x WINDOW_HEIGHT) lands in the real window.
+// Layout code keeps working in virtual units; this maps them onto the window.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub virtual_size: [f32; 2],
+    pub mode: ScaleMode,
+    window_size: [f32; 2],
+    // [x, y, w, h] in window pixels, y down; hangs off the window in Fill mode
+    rect: [f32; 4],
+}
+
+impl Viewport {
+    pub fn new(virtual_size: [f32; 2], mode: ScaleMode, window_size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let mut viewport = Self {
+            virtual_size,
+            mode,
+            window_size: [1.0, 1.0],
+            rect: [0.0, 0.0, 1.0, 1.0],
+        };
+        viewport.resize(window_size);
+        viewport
+    }
+
+    pub fn set_mode(&mut self, mode: ScaleMode) {
+        self.mode = mode;
+        self.layout();
+    }
+
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.window_size = [size.width.max(1) as f32, size.height.max(1) as f32];
+        self.layout();
+    }
+
+    fn layout(&mut self) {
+        let [ww, wh] = self.window_size;
+        let [vw, vh] = self.virtual_size;
+        let fit = (ww / vw).min(wh / vh);
+        let (w, h) = match self.mode {
+            ScaleMode::Stretch => (ww, wh),
+            ScaleMode::Fit => (vw * fit, vh * fit),
+            ScaleMode::Fill => {
+                let fill = (ww / vw).max(wh / vh);
+                (vw * fill, vh * fill)
+            }
+            ScaleMode::IntegerScale => {
+                // a window smaller than the virtual screen can't go below 1x, so just fit it
+                let scale = if fit >= 1.0 { fit.floor() } else { fit };
+                (vw * scale, vh * scale)
+            }
+        };
+        self.rect = [((ww - w) / 2.0).round(), ((wh - h) / 2.0).round(), w, h];
+    }
+
+    pub fn window_size(&self) -> [f32; 2] {
+        self.window_size
+    }
+
+    pub fn rect(&self) -> [f32; 4] {
+        self.rect
+    }
+
+    // the part of rect that's actually inside the window
+    pub fn visible_rect(&self) -> [f32; 4] {
+        let [x, y, w, h] = self.rect;
+        let x0 = x.max(0.0);
+        let y0 = y.max(0.0);
+        let x1 = (x + w).min(self.window_size[0]);
+        let y1 = (y + h).min(self.window_size[1]);
+        [x0, y0, x1 - x0, y1 - y0]
+    }
+
+    // draw only into the virtual screen; everything else stays the clear color (the letterbox bars)
+    pub fn apply(&self, rpass: &mut wgpu::RenderPass) {
+        let [x, y, w, h] = self.visible_rect();
+        rpass.set_viewport(x, y, w, h, 0.0, 1.0);
+        rpass.set_scissor_rect(x as u32, y as u32, w as u32, h as u32);
+    }
+
+    // Narrow a camera that covers the whole virtual screen down to the part that's visible.
+    // Only does anything in Fill mode, where some of the virtual screen is cropped off.
+    pub fn crop_camera(&self, camera: GPUCamera) -> GPUCamera {
+        let [x, y, w, h] = self.rect;
+        let [vx, vy, vw, vh] = self.visible_rect();
+        // fractions of rect that are visible; window y runs down but world y runs up
+        let left = (vx - x) / w;
+        let right = (vx + vw - x) / w;
+        let bottom = (y + h - (vy + vh)) / h;
+        let top = (y + h - vy) / h;
+        GPUCamera {
+            screen_pos: [
+                camera.screen_pos[0] + left * camera.screen_size[0],
+                camera.screen_pos[1] + bottom * camera.screen_size[1],
+            ],
+            screen_size: [
+                camera.screen_size[0] * (right - left),
+                camera.screen_size[1] * (top - bottom),
+            ],
+        }
+    }
+
+    // scale and offset (in -1..1 units) that make a full-screen quad cover rect
+    // instead of just the visible part of it; see Overlay in shader2.wgsl
+    pub fn overlay_transform(&self) -> ([f32; 2], [f32; 2]) {
+        let [x, y, w, h] = self.rect;
+        let [vx, vy, vw, vh] = self.visible_rect();
+        let scale = [w / vw, h / vh];
+        let offset = [
+            ((x + w / 2.0) - (vx + vw / 2.0)) / vw * 2.0,
+            -((y + h / 2.0) - (vy + vh / 2.0)) / vh * 2.0,
+        ];
+        (scale, offset)
+    }
+
+    // window pixels to virtual screen pixels (both y down)
+    pub fn window_to_virtual(&self, pos: MousePos<f64>) -> MousePos<f64> {
+        let [x, y, w, h] = self.rect;
+        MousePos {
+            x: (pos.x - x as f64) / w as f64 * self.virtual_size[0] as f64,
+            y: (pos.y - y as f64) / h as f64 * self.virtual_size[1] as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalSize;
+
+    // a 100x50 virtual screen
+    fn viewport(mode: ScaleMode, w: u32, h: u32) -> Viewport {
+        Viewport::new([100.0, 50.0], mode, PhysicalSize::new(w, h))
+    }
+
+    #[test]
+    fn fit_letterboxes_the_short_side() {
+        // too tall: bars top and bottom
+        let tall = viewport(ScaleMode::Fit, 200, 200);
+        assert_eq!(tall.rect(), [0.0, 50.0, 200.0, 100.0]);
+        assert_eq!(tall.visible_rect(), tall.rect());
+        // too wide: bars left and right
+        let wide = viewport(ScaleMode::Fit, 400, 100);
+        assert_eq!(wide.rect(), [100.0, 0.0, 200.0, 100.0]);
+        assert_eq!(viewport(ScaleMode::Stretch, 400, 100).rect(), [0.0, 0.0, 400.0, 100.0]);
+    }
+
+    #[test]
+    fn integer_scale_rounds_down_to_whole_scales() {
+        // 2.5x would fit, 2x is used and centered
+        let vp = viewport(ScaleMode::IntegerScale, 250, 150);
+        assert_eq!(vp.rect(), [25.0, 25.0, 200.0, 100.0]);
+        // smaller than 1x falls back to fitting
+        let small = viewport(ScaleMode::IntegerScale, 50, 50);
+        assert_eq!(small.rect(), [0.0, 13.0, 50.0, 25.0]);
+    }
+
+    #[test]
+    fn fill_crops_and_visible_rect_clips_to_the_window() {
+        let mut vp = viewport(ScaleMode::Fill, 200, 200);
+        assert_eq!(vp.rect(), [-100.0, 0.0, 400.0, 200.0]);
+        assert_eq!(vp.visible_rect(), [0.0, 0.0, 200.0, 200.0]);
+        vp.set_mode(ScaleMode::Fit);
+        assert_eq!(vp.visible_rect(), [0.0, 50.0, 200.0, 100.0]);
+    }
+
+    #[test]
+    fn crop_camera_keeps_the_visible_middle() {
+        let camera = GPUCamera {
+            screen_pos: [0.0, 0.0],
+            screen_size: [100.0, 50.0],
+        };
+        // Fill shows the middle half of the width
+        let cropped = viewport(ScaleMode::Fill, 200, 200).crop_camera(camera);
+        assert_eq!(cropped.screen_pos, [25.0, 0.0]);
+        assert_eq!(cropped.screen_size, [50.0, 50.0]);
+        // nothing is cropped in Fit
+        let fit = viewport(ScaleMode::Fit, 200, 200).crop_camera(camera);
+        assert_eq!(fit.screen_pos, camera.screen_pos);
+        assert_eq!(fit.screen_size, camera.screen_size);
+    }
+
+    #[test]
+    fn window_to_virtual_maps_through_the_bars() {
+        let vp = viewport(ScaleMode::Fit, 200, 200);
+        let corner = vp.window_to_virtual(MousePos { x: 0.0, y: 50.0 });
+        assert_eq!((corner.x, corner.y), (0.0, 0.0));
+        let middle = vp.window_to_virtual(MousePos { x: 100.0, y: 100.0 });
+        assert_eq!((middle.x, middle.y), (50.0, 25.0));
+        // in the bars the mouse is off the virtual screen, not clamped onto it
+        let above = vp.window_to_virtual(MousePos { x: 100.0, y: 10.0 });
+        assert_eq!((above.x, above.y), (50.0, -20.0));
+        let below = vp.window_to_virtual(MousePos { x: 200.0, y: 190.0 });
+        assert_eq!((below.x, below.y), (100.0, 70.0));
+    }
+}
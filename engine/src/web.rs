@@ -0,0 +1,35 @@
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::platform::web::WindowExtWebSys;
+use winit::window::Window;
+
+// Put the game's canvas in the page: inside the element with id "game" if there is one,
+// otherwise at the end of the body.
+pub fn attach_canvas(window: &Window) {
+    let canvas = web_sys::Element::from(window.canvas());
+    let document = web_sys::window().and_then(|win| win.document());
+    let parent = document.and_then(|doc| {
+        doc.get_element_by_id("game")
+            .or_else(|| doc.body().map(web_sys::Element::from))
+    });
+    match parent {
+        Some(parent) if parent.append_child(&canvas).is_ok() => fit_canvas(window),
+        _ => log::error!("Couldn't add the canvas to the page"),
+    }
+}
+
+// Size the canvas to the browser window, which winit doesn't do by itself. Call once a
+// frame; when it changes the usual Resized event follows.
+pub fn fit_canvas(window: &Window) {
+    let Some(win) = web_sys::window() else {
+        return;
+    };
+    let width = win.inner_width().ok().and_then(|w| w.as_f64());
+    let height = win.inner_height().ok().and_then(|h| h.as_f64());
+    if let (Some(width), Some(height)) = (width, height) {
+        let size = LogicalSize::new(width, height);
+        let physical: PhysicalSize<u32> = size.to_physical(window.scale_factor());
+        if window.inner_size() != physical {
+            window.set_inner_size(size);
+        }
+    }
+}
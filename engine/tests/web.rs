@@ -0,0 +1,35 @@
+// Headless checks of the parts of the engine that behave differently in the browser:
+//     wasm-pack test --node engine
+#![cfg(target_arch = "wasm32")]
+
+use std::path::Path;
+use engine::vfs::{self, Source};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+static FILES: &[(&str, &[u8])] = &[
+    ("content/sprites-overlay.png", include_bytes!("../../content/sprites-overlay.png")),
+    ("content/player_hit.ogg", include_bytes!("../../content/player_hit.ogg")),
+];
+
+#[wasm_bindgen_test]
+fn embedded_files_read_and_list() {
+    vfs::mount(Source::Embedded(FILES));
+    let bytes = vfs::read(Path::new("./content/player_hit.ogg")).unwrap();
+    assert_eq!(&bytes[..], FILES[1].1);
+    assert_eq!(vfs::list(Path::new("content")).len(), 2);
+    assert!(vfs::read(Path::new("content/missing.png")).is_err());
+}
+
+#[wasm_bindgen_test]
+fn embedded_images_decode() {
+    vfs::mount(Source::Embedded(FILES));
+    let bytes = vfs::read(Path::new("content/sprites-overlay.png")).unwrap();
+    let img = image::load_from_memory(&bytes).unwrap();
+    assert!(img.width() > 0 && img.height() > 0);
+}
+
+#[wasm_bindgen_test]
+fn instant_works() {
+    let start = engine::Instant::now();
+    assert!(start.elapsed().as_secs_f32() >= 0.0);
+}
@@ -1,29 +1,68 @@
-use std::{borrow::Cow, mem, path::Path};
+use std::{borrow::Cow, path::Path};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
-use std::time::Instant;
 mod sprites;
 use engine;
 use engine::{WINDOW_WIDTH, WINDOW_HEIGHT, NUMBER_OF_CELLS_W, NUMBER_OF_CELLS_H, CELL_WIDTH, CELL_HEIGHT};
+use engine::{AppConfig, Instant};
 use engine::input::Input;
 use engine::gpu::{WGPU, CAMERALAYOUT};
-use engine::sprite::{SPRITES, SpriteOption, GPUCamera, GPUSprite}; 
+use engine::sprite::{overlay_pipeline, sprite_pipeline, GPUOverlay, Layer, SpriteList, SpriteBuffer, FLIP_X};
+use engine::texture::{TextureOptions, TextureRegistry};
+use engine::background::BackgroundLayer;
+use engine::postfx::{Effect, PostFx};
+use engine::transition::{Transition, TransitionKind};
+use engine::capture::Capture;
+use engine::debug::{self, DebugOverlay};
+use engine::assets::Assets;
+use engine::hotreload::{AssetWatcher, ShaderWatcher};
+use engine::lighting::{Light, Lighting};
+use engine::particles::{particle_texture, Emitter, EmitterConfig, ParticleSystem, DOT_FRAME};
 use engine::gamestate::GameState; 
-use engine::sound::{play_sound};
+use engine::sound;
+use engine::anim::AnimEvent;
+use engine::tween::{Ease, SpriteField, Tween, Tweener};
+use engine::camera::Camera;
 
 // how fast movable sprites move per sec 
 pub const SPEED: f32 = 0.5;
 
-async fn run(event_loop: EventLoop<()>, window: Window) {
+// post-processing for each game state: a CRT look on the title, a vignette in the
+// store, and a washed-out game over
+fn scene_effects(state: usize) -> Vec<Effect> {
+    let vignette = Effect::Vignette { strength: 0.6, radius: 0.55, softness: 0.5 };
+    match state {
+        0 => vec![Effect::Crt { scanlines: 0.3, curvature: 0.06 }],
+        2 => vec![
+            Effect::ColorGrade { brightness: -0.05, contrast: 1.1, saturation: 0.3, tint: [1.0, 0.9, 0.9] },
+            vignette,
+        ],
+        _ => vec![vignette],
+    }
+}
+
+// how the screen changes between game states: wipe into the store, close in on
+// getting caught, fade everywhere else
+fn scene_transition(from: usize, to: usize) -> TransitionKind {
+    match (from, to) {
+        (0, 1) => TransitionKind::Wipe { angle: 0.0, softness: 0.15 },
+        (_, 2) => TransitionKind::Iris { center: [0.5, 0.5], color: [0.0, 0.0, 0.0] },
+        _ => TransitionKind::Fade { color: [0.0, 0.0, 0.0] },
+    }
+}
+
+async fn run(event_loop: EventLoop<()>, window: Window, mut app: AppConfig) {
 
     let mut game_state = GameState { state: 0 };
 
-    log::info!("Use sprite mode {:?}", SPRITES);
-    
-    let mut gpu = WGPU::new(&window).await;
+    let mut gpu = match WGPU::new(&window, &app).await {
+        Ok(gpu) => gpu,
+        Err(e) => return engine::error::report(&e),
+    };
+    let sprite_option = gpu.sprite_option;
     
     // Load the shaders from disk
     let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -36,299 +75,122 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../engine/src/shader2.wgsl"))),
     });
 
-    let texture_bind_group_layout =
-        gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            // It needs the first entry for the texture and the second for the sampler.
-            // This is like defining a type signature.
-            entries: &[
-                // The texture binding
-                wgpu::BindGroupLayoutEntry {
-                    // This matches the binding in the shader
-                    binding: 0,
-                    // Only available in the fragment shader
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // It's a texture binding
-                    ty: wgpu::BindingType::Texture {
-                        // We can use it with float samplers
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        // It's being used as a 2D texture
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        // This is not a multisampled texture
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                // The sampler binding
-                wgpu::BindGroupLayoutEntry {
-                    // This matches the binding in the shader
-                    binding: 1,
-                    // Only available in the fragment shader
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // It's a sampler
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    // No count
-                    count: None,
-                },
-            ],
-        });
+    let mut textures = TextureRegistry::new(&gpu);
 
-    let sprite_bind_group_layout = match SPRITES {
-        SpriteOption::Storage => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    CAMERALAYOUT,
-                    wgpu::BindGroupLayoutEntry {
-                        // This matches the binding in the shader
-                        binding: 1,
-                        // Available in vertex shader
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        // It's a buffer
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        // No count, not a buffer array binding
-                        count: None,
-                    },
-                ],
-            })
-        }
-        SpriteOption::Uniform => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    CAMERALAYOUT,
-                    wgpu::BindGroupLayoutEntry {
-                        // This matches the binding in the shader
-                        binding: 1,
-                        // Available in vertex shader
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        // It's a buffer
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(SPRITE_UNIFORM_SIZE),
-                        },
-                        // No count, not a buffer array binding
-                        count: None,
-                    },
-                ],
-            })
-        }
-        SpriteOption::VertexBuffer => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[CAMERALAYOUT],
-            })
-        }
-    };
+    let mut sprite_buffer = SpriteBuffer::new(&gpu);
     let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&sprite_bind_group_layout, &texture_bind_group_layout],
+        bind_group_layouts: &[&sprite_buffer.layout, &textures.layout],
         push_constant_ranges: &[],
     });
 
-    let pipeline_layout_over = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    // full-screen images get an offset uniform so they can slide around
+    let overlay_bind_group_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&texture_bind_group_layout],
-        push_constant_ranges: &[],
+        entries: &[CAMERALAYOUT],
     });
 
-    let render_pipeline_full = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    let pipeline_layout_over = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        layout: Some(&pipeline_layout_over),
-        vertex: wgpu::VertexState {
-            module: &shader2,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader2,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
+        bind_group_layouts: &[&textures.layout, &overlay_bind_group_layout],
+        push_constant_ranges: &[],
     });
 
-    let render_pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: match SPRITES {
-                SpriteOption::Storage => "vs_storage_main",
-                SpriteOption::Uniform => "vs_uniform_main",
-                SpriteOption::VertexBuffer => "vs_vbuf_main",
-            },
-            buffers: match SPRITES {
-                SpriteOption::VertexBuffer => &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<GPUSprite>() as u64,
-                    step_mode: wgpu::VertexStepMode::Instance,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            format: wgpu::VertexFormat::Float32x4,
-                            offset: 0,
-                            shader_location: 0,
-                        },
-                        wgpu::VertexAttribute {
-                            format: wgpu::VertexFormat::Float32x4,
-                            offset: std::mem::size_of::<[f32; 4]>() as u64,
-                            shader_location: 1,
-                        },
-                    ],
-                }],
-                _ => &[],
-            },
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
+    let mut render_pipeline_full = overlay_pipeline(&gpu, &pipeline_layout_over, &shader2);
+
+    let mut render_pipeline = sprite_pipeline(&gpu, &pipeline_layout, &shader, sprite_option);
+    // in development builds, saving either shader rebuilds its pipeline
+    let mut shaders = ShaderWatcher::new(app.hot_reload);
+    let sprite_shader = shaders.watch("engine/src/shader.wgsl");
+    let overlay_shader = shaders.watch("engine/src/shader2.wgsl");
+    // and saving anything under content/ reloads it
+    let mut content = AssetWatcher::new("content", app.hot_reload);
+    let mut asset_error: Option<String> = None;
 
     gpu.surface.configure(&gpu.device, &gpu.config);
+    // everything from content/ loads in the background behind a loading bar
+    let mut assets = Assets::new();
     let path_sprites = Path::new("content/sprites-overlay.png");
-    let (sprite_tex, _sprite_img) = gpu.load_texture(path_sprites, None)
-        .await
-        .expect("Couldn't load spritesheet texture");
-    let view_sprite = sprite_tex.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_sprite = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-    let texture_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+    // first in the registry, so it's the texture sprites get by default
+    assets.load_texture(&gpu, &mut textures, path_sprites, TextureOptions::pixel_art());
+    // the store is one screen for now, so following the player only matters once it's zoomed in
+    let mut camera = Camera::new([WINDOW_WIDTH, WINDOW_HEIGHT]);
+    camera.bounds = Some([0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT]);
+    camera.deadzone = [2.0 * CELL_WIDTH, 2.0 * CELL_HEIGHT];
+    camera.follow_rate = 8.0;
+    let buffer_camera = gpu.device.create_buffer(&wgpu::BufferDescriptor {
         label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_sprite),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_sprite),
-            },
-        ],
+        size: bytemuck::bytes_of(&camera.gpu_camera()).len() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
     });
-    let camera = GPUCamera {
-        screen_pos: [0.0, 0.0],
-        screen_size: [1024.0, 768.0],
-    };
-    let buffer_camera = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+
+    let mut overlay = GPUOverlay::default();
+    let buffer_overlay = gpu.device.create_buffer(&wgpu::BufferDescriptor {
         label: None,
-        size: bytemuck::bytes_of(&camera).len() as u64,
+        size: bytemuck::bytes_of(&overlay).len() as u64,
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
+    let overlay_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &overlay_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer_overlay.as_entire_binding(),
+        }],
+    });
 
-    let mut sprites: Vec<GPUSprite> = sprites::create_sprites();
+    let mut sprites: SpriteList = sprites::create_sprites();
+
+    // "after hours" (N on the title): the store is dark apart from the associates'
+    // flashlights and the exit sign, and getting caught in a flashlight ends the game
+    let mut after_hours = false;
+    let associates = sprites::associates(&sprites);
+    let mut lighting = Lighting::new(&gpu, sprites::occluders(&sprites));
+    lighting.ambient = [0.12, 0.12, 0.2];
+    for _ in &associates {
+        lighting.lights.push(Light {
+            radius: 6.0 * CELL_WIDTH,
+            color: [1.0, 0.95, 0.8],
+            cone: 0.45,
+            ..Light::default()
+        });
+    }
+    let [door_x, door_y, door_w, door_h] = sprites[57].screen_region;
+    lighting.lights.push(Light {
+        position: [door_x + door_w / 2.0, door_y + door_h / 2.0],
+        radius: 4.0 * CELL_WIDTH,
+        color: [0.3, 1.0, 0.5],
+        intensity: 0.8,
+        ..Light::default()
+    });
 
     // Initialize sprite position within the grid
     let mut sprite_position: [f32; 2] = [10.0 * CELL_WIDTH, 2.0 * CELL_HEIGHT];  
 
-    const SPRITE_UNIFORM_SIZE: u64 = 512 * mem::size_of::<GPUSprite>() as u64;
-    let buffer_sprite = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: if SPRITES == SpriteOption::Uniform {
-            SPRITE_UNIFORM_SIZE
-        } else {
-            sprites.len() as u64 * std::mem::size_of::<GPUSprite>() as u64
-        },
-        usage: match SPRITES {
-            SpriteOption::Storage => wgpu::BufferUsages::STORAGE,
-            SpriteOption::Uniform => wgpu::BufferUsages::UNIFORM,
-            SpriteOption::VertexBuffer => wgpu::BufferUsages::VERTEX,
-        } | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-    let sprite_bind_group = match SPRITES {
-        SpriteOption::VertexBuffer => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &sprite_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer_camera.as_entire_binding(),
-            }],
-        }),
-        _ => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &sprite_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer_camera.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: buffer_sprite.as_entire_binding(),
-                },
-            ],
-        }),
-    };
 
     // create background stuff
     let path_bgnd = Path::new("content/space.jpeg");
-    let (tex_bgnd, _over_image) = gpu.load_texture(path_bgnd,None)
-        .await
-        .expect("Couldn't load space img");
+    // mirrored so the drifting starfield has no seams
+    let bgnd_options = TextureOptions {
+        address_mode: wgpu::AddressMode::MirrorRepeat,
+        ..TextureOptions::default()
+    };
+    let tex_bgnd = assets.load_texture(&gpu, &mut textures, path_bgnd, bgnd_options);
+    let mut background = BackgroundLayer::new(&gpu, &overlay_bind_group_layout, tex_bgnd, [WINDOW_WIDTH, WINDOW_HEIGHT]);
+    background.parallax = [0.5, 0.5];
+    background.scroll = [0.01, 0.0];
 
-    let view_bgnd = tex_bgnd.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_bgnd = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-    
     // create title
     let path_title = Path::new("content/screen-page.png");
-    let (tex_title, _over_image) = gpu.load_texture(path_title,None)
-        .await
-        .expect("Couldn't load space img");
-
-    let view_title = tex_title.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_title = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-        
-    // set first background to instructions
-    let mut texture_bind_group_bgnd2 = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_title),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_title),
-            },
-        ],
-    });
+    let tex_title = assets.load_texture(&gpu, &mut textures, path_title, TextureOptions::default());
 
-    let mut texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_bgnd),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_bgnd),
-            },
-        ],
-    });
 
-    gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-    gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
+    gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&gpu.viewport.crop_camera(camera.gpu_camera())));
+    sprite_buffer.upload(&gpu, &buffer_camera, &sprites);
     let mut input = Input::default();
+    input.set_viewport(gpu.viewport);
     let mut game_over = false; 
     let mut prev_t = Instant::now();
     let mut collided_wall = false;
@@ -338,18 +200,40 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     let mut aisle_bottom = false;
     let mut aisle_right = false;
     let mut aisle_left = false;
+    let mut player_anim = sprites::player_animator();
+    let mut prev_frame = Instant::now();
+    let mut tweens = Tweener::default();
+    // sparkles when an item goes in the cart
+    let mut particles = ParticleSystem::default();
+    let sparkles = particles.add(Emitter::new(EmitterConfig {
+        lifetime: [0.4, 0.7],
+        speed: [80.0, 180.0],
+        gravity: [0.0, -250.0],
+        size: [12.0, 12.0],
+        end_scale: 0.3,
+        colors: vec![[1.0, 0.95, 0.5, 1.0], [1.0, 1.0, 1.0, 1.0]],
+        frames: vec![DOT_FRAME],
+        texture: particle_texture(&gpu, &mut textures),
+        ..EmitterConfig::default()
+    }));
+    // the cell the player is sliding toward
+    let mut player_target = [sprites[0].screen_region[0], sprites[0].screen_region[1]];
+    let mut shown_state = game_state.state;
+    let mut postfx = PostFx::new(&gpu, &textures);
+    postfx.effects = scene_effects(shown_state);
+    let mut transition = Transition::new(&gpu);
+    let mut capture = Capture::default();
+    let mut debug_overlay = DebugOverlay::new(&gpu, &mut textures);
+    let mut end_slide = Tween::new(0.0, 0.0, 0.0, Ease::Linear);
 
     let path_win = Path::new("content/youWin.png");
 
    //LOAD TEXTURE
-    let (tex_win, _win_image) = gpu.load_texture(path_win,None)
-        .await
-        .expect("Couldn't load game over img");
+    let tex_win = assets.load_texture(&gpu, &mut textures, path_win, TextureOptions::default());
     
     let path_over = Path::new("content/gameOver.png");
-    let (tex_over, _over_image) = gpu.load_texture(path_over,None)
-        .await
-        .expect("Couldn't load game over img");
+    let tex_over = assets.load_texture(&gpu, &mut textures, path_over, TextureOptions::default());
+    let hit_sound = assets.load_sound(Path::new("content/player_hit.ogg"));
 
     event_loop.run(move |event, _, control_flow| {
 
@@ -360,15 +244,45 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 ..
             } => {
                 gpu.resize(size);
+                input.set_viewport(gpu.viewport);
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
-                if game_over {
-                    sprites[0].screen_region[1] -= 5.0;
-                    if sprites[0].screen_region[1] < 0.0 {
+                let dt = prev_frame.elapsed().as_secs_f32();
+                prev_frame = Instant::now();
+                let update_start = Instant::now();
+                #[cfg(target_arch = "wasm32")]
+                engine::web::fit_canvas(&window);
+                debug_overlay.frame(dt);
+                let failed = assets.errors().len();
+                assets.update(&gpu, &mut textures);
+                debug_overlay.set_loading((!assets.is_done()).then(|| assets.progress()));
+                if let Some(error) = assets.errors()[failed..].last() {
+                    asset_error = Some(error.to_string());
+                    debug_overlay.set_error(shaders.error().or(asset_error.as_deref()));
+                }
+
+                if let Some(AnimEvent::Finished(clip)) = player_anim.update(dt, &mut sprites[0]) {
+                    if clip == "caught" {
                         game_state.state = 2; 
                     }
                 }
+                if player_anim.current() == Some("caught") {
+                    sprites[0].set_alpha(sprites::caught_blink(&player_anim));
+                }
+
+                if game_over {
+                    // flash red and spin out the first time through
+                    if player_anim.current() != Some("caught") {
+                        tweens.cancel(0);
+                        sprites[0].color = [1.0, 0.3, 0.3, 1.0];
+                        camera.shake(12.0, 0.5);
+                        postfx.flash([1.0, 0.2, 0.2], 0.4);
+                        tweens.add(0, SpriteField::Rotation, Tween::new(0.0, 2.0 * std::f32::consts::PI, 0.84, Ease::CubicOut));
+                    }
+                    sprites[0].screen_region[1] -= 5.0;
+                    player_anim.play("caught");
+                }
 
                 else {
                     // collision sprites
@@ -386,10 +300,13 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                             if elapsed > SPEED { 
                                 if right {
                                     sprites[i].screen_region[0] += 1.0 * CELL_WIDTH;
+                                    sprites[i].set_flip(false, false);
                                     prev_t = Instant::now();
                                 }
                                 else {
+                                    // face the way they're walking
                                     sprites[i].screen_region[0] -= 1.0 * CELL_WIDTH;
+                                    sprites[i].set_flip(true, false);
                                     prev_t = Instant::now();
                                 }
 
@@ -439,23 +356,24 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                             for (cx, cy, c) in corners.iter(){
                                 if sprites[i].screen_region[0].floor() == sprites[0].screen_region[0].floor() 
                                 && sprites[i].screen_region[1].floor() == (sprites[0].screen_region[1] + CELL_HEIGHT).floor() {
-                                    if sprites[i].sheet_region[1] != 64.0 {
-                                        play_sound();             
+                                    if sprites[i].sheet_region[1] != 64.0 && !tweens.is_tweening(i) {
+                                        if let Some(Err(e)) = assets.sound(hit_sound).map(sound::play) {
+                                            log::warn!("{}", e);
+                                        }
+                                        let [x, y, w, h] = sprites[i].screen_region;
+                                        particles.emitter(sparkles).burst_at([x + w / 2.0, y + h / 2.0], 14);
+                                        // bananas, carrots, bread, salad, cereal, ketchup, potato chips
+                                        let check = match i { 74 => 99, 77 => 101, 81 => 100, 84 => 102, 88 => 103, 91 => 104, _ => 105 };
+                                        // bounce the checkmark onto the grocery list
+                                        sprites[check].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2];
+                                        let y = sprites[check].screen_region[1];
+                                        tweens.add(check, SpriteField::ScreenY, Tween::new(y + 0.5 * CELL_HEIGHT, y, 0.6, Ease::BounceOut));
+                                        // fade the item out as it goes in the cart, then take it off the shelf
+                                        tweens.add_then(i, SpriteField::Alpha, Tween::new(1.0, 0.0, 0.3, Ease::QuadIn), move |sprites| {
+                                            sprites[i].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];
+                                            sprites[i].set_alpha(1.0);
+                                        });
                                     }  
-                                    //bananas
-                                    if i == 74 { sprites[99].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[74].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //carrots
-                                    if i == 77 { sprites[101].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[77].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //cereal 
-                                    if i == 88 { sprites[103].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[88].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //ketchup
-                                    if i == 91 { sprites[104].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[91].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //bread 
-                                    if i == 81 { sprites[100].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[81].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //salad
-                                    if i == 84 { sprites[102].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[84].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
-                                    //potato chips
-                                    if i == 95 { sprites[105].sheet_region = [0.0, 70.0/320.0, 64.0/1408.0, 0.2]; sprites[95].sheet_region = [0.0, 64.0, 64.0/1408.0, 0.2];}
                                 }   
                             }
                         } 
@@ -467,9 +385,34 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     
                     // move sprite based on input
                     sprite_position = sprites::move_sprite_input(&input, sprite_position, collided_wall, at_door, aisle_left, aisle_right, aisle_top, aisle_bottom);
-                    if input.is_key_pressed(winit::event::VirtualKeyCode::Space) {
+                    // nothing starts until the screens have loaded
+                    if assets.is_done() && input.is_key_pressed(winit::event::VirtualKeyCode::Space) {
                         game_state.state = 1
                     }
+                    if game_state.state == 0 && assets.is_done() && input.is_key_pressed(winit::event::VirtualKeyCode::N) {
+                        after_hours = true;
+                        game_state.state = 1;
+                    }
+
+                    if after_hours {
+                        // flashlights point the way each associate is facing
+                        for (light, &i) in lighting.lights.iter_mut().zip(&associates) {
+                            let [x, y, w, h] = sprites[i].screen_region;
+                            light.position = [x + w / 2.0, y + h / 2.0];
+                            let facing_left = sprites[i].transform[3] as u32 & FLIP_X != 0;
+                            light.direction = if facing_left { std::f32::consts::PI } else { 0.0 };
+                        }
+                        // standing in an associate's flashlight gets you caught
+                        let [x, y, w, h] = sprites[0].screen_region;
+                        let player = [x + w / 2.0, y + h / 4.0];
+                        let spotted = (0..associates.len()).any(|n| {
+                            let [r, g, b] = lighting.light_from(n, player);
+                            r.max(g).max(b) > 0.3
+                        });
+                        if game_state.state == 1 && spotted {
+                            game_over = true;
+                        }
+                    }
                     
                     aisle_left = false;
                     aisle_right = false;
@@ -481,20 +424,117 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         game_state.state = 3;
                     }
 
-                    //update sprite position            
-                    sprites[0].screen_region[0] = sprite_position[0];
-                    sprites[0].screen_region[1] = sprite_position[1];  
+                    //update sprite position, sliding into the new cell instead of jumping
+                    if sprite_position != player_target {
+                        tweens.add(0, SpriteField::ScreenX, Tween::new(sprites[0].screen_region[0], sprite_position[0], 0.12, Ease::QuadOut));
+                        tweens.add(0, SpriteField::ScreenY, Tween::new(sprites[0].screen_region[1], sprite_position[1], 0.12, Ease::QuadOut));
+                        player_target = sprite_position;
+                    }
                 }
                 
+                tweens.update(dt, &mut sprites);
+                particles.update(dt);
+                let player = sprites[0].screen_region;
+                camera.follow([player[0] + player[2] / 2.0, player[1] + player[3] / 2.0], dt);
+                camera.update(dt);
+
+                app.handle_hotkeys(&input, &window);
+                capture.handle_hotkeys(&input);
+                debug_overlay.handle_hotkeys(&input);
+
                 // Then send the data to the GPU!
                 input.next_frame();
 
-                gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-                gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
-
-                let frame = gpu.surface
-                    .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
+                // slide the end screens in from the top when they first show up
+                if game_state.state != shown_state {
+                    transition.start_with(scene_transition(shown_state, game_state.state), 0.6);
+                    shown_state = game_state.state;
+                    postfx.effects = scene_effects(shown_state);
+                    if shown_state >= 2 {
+                        end_slide = Tween::new(2.0, 0.0, 0.8, Ease::BounceOut);
+                    }
+                }
+                let slide = if game_state.state >= 2 { end_slide.update(dt) } else { 0.0 };
+                let (overlay_scale, overlay_offset) = gpu.viewport.overlay_transform();
+                overlay.scale = overlay_scale;
+                overlay.offset = [overlay_offset[0], overlay_offset[1] + slide * overlay_scale[1]];
+
+                gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&gpu.viewport.crop_camera(camera.gpu_camera())));
+                background.update(&gpu, dt, gpu.viewport.crop_camera(camera.gpu_camera()));
+                if after_hours {
+                    lighting.update(&gpu, gpu.viewport.crop_camera(camera.gpu_camera()));
+                }
+                gpu.queue.write_buffer(&buffer_overlay, 0, bytemuck::bytes_of(&overlay));
+                // particles only live for this upload, after the game's own sprites
+                let game_sprites = sprites.len();
+                particles.push_sprites(&mut sprites);
+                sprite_buffer.upload(&gpu, &buffer_camera, &sprites);
+                sprites.truncate(game_sprites);
+
+                // collision boxes and what the associates can see, when the overlay is up
+                if debug_overlay.visible && game_state.state == 1 {
+                    debug_overlay.grid([0.0, 0.0], [CELL_WIDTH, CELL_HEIGHT], NUMBER_OF_CELLS_W as usize, NUMBER_OF_CELLS_H as usize, debug::GREY);
+                    for wall in &sprites[1..71] {
+                        debug_overlay.rect(wall.screen_region, debug::RED);
+                    }
+                    for &i in &associates {
+                        debug_overlay.rect(sprites[i].screen_region, debug::YELLOW);
+                    }
+                    debug_overlay.rect(sprites[0].screen_region, debug::GREEN);
+                    if after_hours {
+                        for light in &lighting.lights[..associates.len()] {
+                            debug_overlay.cone(light.position, light.direction, light.cone, light.radius, debug::CYAN);
+                        }
+                    }
+                }
+                let shaders_changed = shaders.poll(dt);
+                if shaders_changed {
+                    if let Some(pipeline) = shaders.reload(&gpu, sprite_shader, |m| sprite_pipeline(&gpu, &pipeline_layout, m, sprite_option)) {
+                        render_pipeline = pipeline;
+                    }
+                    if let Some(pipeline) = shaders.reload(&gpu, overlay_shader, |m| overlay_pipeline(&gpu, &pipeline_layout_over, m)) {
+                        render_pipeline_full = pipeline;
+                    }
+                }
+                // images are updated in place; sounds, text and atlases load again in the
+                // background and are swapped in by assets.update
+                for path in assets.take_reloaded() {
+                    log::info!("Reloaded {}", path.display());
+                }
+                let changed_assets = content.poll(dt);
+                if !changed_assets.is_empty() {
+                    asset_error = None;
+                }
+                for path in &changed_assets {
+                    assets.reload(path);
+                    if let Err(e) = textures.reload(&gpu, path) {
+                        log::warn!("{}", e);
+                        asset_error = Some(e.to_string());
+                    }
+                }
+                if shaders_changed || !changed_assets.is_empty() {
+                    debug_overlay.set_error(shaders.error().or(asset_error.as_deref()));
+                }
+                debug_overlay.set_update_time(update_start.elapsed());
+                debug_overlay.upload(&gpu, &buffer_camera, &sprite_buffer);
+                let render_start = Instant::now();
+
+                postfx.update(&gpu, dt);
+                transition.update(&gpu, dt);
+                capture.update(dt);
+
+                let frame = match gpu.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => {
+                        window.request_redraw();
+                        return;
+                    }
+                    Err(e) => {
+                        engine::error::report(&e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                };
                 let view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
@@ -505,7 +545,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: None,
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: postfx.scene_view(transition.scene_view(&view)),
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
@@ -514,85 +554,58 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         })],
                         depth_stencil_attachment: None,
                     });
+                    // letterbox the game screen into the window
+                    gpu.viewport.apply(&mut rpass);
 
                     match game_state.state {
                         0 => {
                             // draw instructions
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd2, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_title), &[]);
                             rpass.draw(0..6, 0..1);
                         }
                         2 => {                        
-                            let tex_end = &tex_over;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
-    
                             // Draw end game screen
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_over), &[]);
                             rpass.draw(0..6, 0..1);
                         }
                         3 => {
-                            let tex_end = &tex_win;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
-    
                             // Draw end game screen
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_win), &[]);
                             rpass.draw(0..6, 0..1);
                         }
                         _ => {
                             // Draw space background
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
-                            rpass.draw(0..6, 0..1);
+                            background.draw(&mut rpass, &textures);
                             {
-                                rpass.set_pipeline(&render_pipeline);
-                                if SPRITES == SpriteOption::VertexBuffer {
-                                    rpass.set_vertex_buffer(0, buffer_sprite.slice(..));
+                                if after_hours {
+                                    // darken the store but not the grocery list
+                                    sprite_buffer.draw_layers(&mut rpass, &render_pipeline, &textures, ..Layer::Hud);
+                                    lighting.draw(&mut rpass);
+                                    sprite_buffer.draw_layers(&mut rpass, &render_pipeline, &textures, Layer::Hud..);
+                                } else {
+                                    sprite_buffer.draw(&mut rpass, &render_pipeline, &textures);
                                 }
-                                rpass.set_bind_group(0, &sprite_bind_group, &[]);
-                                rpass.set_bind_group(1, &texture_bind_group, &[]);
-                                rpass.draw(0..6, 0..(sprites.len() as u32));
                             }
                         }
                     }
+                    debug_overlay.draw(&mut rpass, &render_pipeline, &textures);
+                }
+                postfx.apply(&gpu, &mut encoder, &textures, transition.scene_view(&view));
+                transition.apply(&gpu, &mut encoder, &view);
+                if let Some(scene) = transition.scene_texture() {
+                    capture.capture(&gpu, &mut encoder, scene);
                 }
                 gpu.queue.submit(Some(encoder.finish()));
+                capture.finish(&gpu);
                 frame.present();
+                debug_overlay.set_render_time(render_start.elapsed());
                 window.request_redraw();
             }
             Event::WindowEvent {
@@ -605,12 +618,16 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 event: WindowEvent::KeyboardInput { input: key_ev, .. },
                 ..
             } => {
+                // the browser only lets audio start once the player has done something
+                // (no audio is reported when a sound plays)
+                let _ = sound::unlock();
                 input.handle_key_event(key_ev);
             }
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { state, button, .. },
                 ..
             } => {
+                let _ = sound::unlock();
                 input.handle_mouse_button(state, button);
             }
             Event::WindowEvent {
@@ -625,27 +642,37 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 }
 
 fn main() {
-    let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
+    #[cfg(feature = "embed")]
+    engine::vfs::mount(engine::vfs::Source::Embedded(engine::embed_files!("../content" as "content":
+        "sprites-overlay.png",
+        "space.jpeg",
+        "screen-page.png",
+        "youWin.png",
+        "gameOver.png",
+        "player_hit.ogg",
+    )));
+    // logging first, so anything below that fails gets reported
     #[cfg(not(target_arch = "wasm32"))]
+    env_logger::init();
+    #[cfg(target_arch = "wasm32")]
     {
-        env_logger::init();
-        pollster::block_on(run(event_loop, window));
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        let _ = console_log::init_with_level(log::Level::Info);
     }
+    let event_loop = EventLoop::new();
+    let app = AppConfig {
+        title: "The Shoplifter".to_string(),
+        ..AppConfig::default()
+    };
+    let window = match app.build_window(&event_loop) {
+        Ok(window) => window,
+        Err(e) => return engine::error::report(&e),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(run(event_loop, window, app));
     #[cfg(target_arch = "wasm32")]
     {
-        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-        console_log::init_with_level(log::Level::Trace).expect("could not initialize logger");
-        use winit::platform::web::WindowExtWebSys;
-        // On wasm, append the canvas to the document body
-        web_sys::window()
-            .and_then(|win| win.document())
-            .and_then(|doc| doc.body())
-            .and_then(|body| {
-                body.append_child(&web_sys::Element::from(window.canvas()))
-                    .ok()
-            })
-            .expect("couldn't append canvas to document body");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+        engine::web::attach_canvas(&window);
+        wasm_bindgen_futures::spawn_local(run(event_loop, window, app));
     }
 }
\ No newline at end of file
@@ -1,59 +1,69 @@
 use rand::Rng;
 use crate::{WINDOW_WIDTH, WINDOW_HEIGHT, NUMBER_OF_CELLS_W, NUMBER_OF_CELLS_H, CELL_WIDTH, CELL_HEIGHT};
 use engine::input::Input;
-use engine::sprite::GPUSprite; 
+use engine::sprite::{GPUSprite, Layer, SpriteList}; 
+use engine::anim::{Animator, Clip, PlayMode};
+use engine::lighting::OccluderGrid;
 
 pub const door_xvalue: i32 = NUMBER_OF_CELLS_W/3; 
 
-pub fn create_sprites() ->  Vec<GPUSprite> {
+pub fn create_sprites() ->  SpriteList {
     // CUSTOMER (AKA PLAYER)
-    let mut sprites: Vec<GPUSprite> = vec![GPUSprite {
+    let mut sprites = SpriteList::new();
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [8.0*CELL_WIDTH, 2.0 * CELL_HEIGHT, CELL_WIDTH, 2.0 * CELL_HEIGHT],
         sheet_region: [384.0/1408.0, 0.0, 64.0/1408.0, 128.0/320.0], 
-    }];
+        ..Default::default()
+    });
 
     //WALLS: sprite[0] to sprite[70]!! (sprite = shelf1)
     for y in 0..NUMBER_OF_CELLS_H {
         let y_value = y as f32 * CELL_HEIGHT;
         if y == 0 {
             for x in 0..NUMBER_OF_CELLS_W {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region: [1088.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                    ..Default::default()
                 });
             }
         }
         // top of the wall & door
         else if y == (NUMBER_OF_CELLS_H - 1) {
             for x in 0..door_xvalue {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region: [1088.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                    ..Default::default()
                 });
             } // empty door (57)
             for x in door_xvalue..door_xvalue + 1 {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region:  [832.0/1408.0, 77.0/320.0, 62.0/1408.0, 64.0/320.0],
+                    ..Default::default()
                 });
                 print!("DOOR: {:#?}", sprites.len());
             } for x in door_xvalue+1..NUMBER_OF_CELLS_W {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region:  [1088.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                    ..Default::default()
                 });
             }
         }
         // sides of the wall 
         else {
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [0 as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                 sheet_region:  [1088.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
             //right side of the wall 
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [(NUMBER_OF_CELLS_W - 1) as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                 sheet_region:  [1088.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
         }
     }
@@ -75,34 +85,41 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
                 coord1 = 448.0; coord2 = 512.0; coord3 = 640.0; coord4 = 704.0;
             }  
             
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + even_x * CELL_WIDTH, even_y * CELL_HEIGHT, CELL_WIDTH, 4.0*CELL_HEIGHT],
                 sheet_region: [1280.0/1408.0, 0.0, 64.0/1408.0, 256.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + even_x * CELL_WIDTH, even_y * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region: [coord1/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + even_x * CELL_WIDTH, (even_y + 1.0) * CELL_HEIGHT, 45.0, 45.0],
                 sheet_region: [coord2/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             }); //FOOD
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + (even_x - num_a) * CELL_WIDTH, (even_y + 1.0) * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
                 sheet_region: [coord2/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             });
             print!("FOOD: {:#?}", sprites.len());
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + even_x * CELL_WIDTH, (even_y + 2.0) * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region: [coord3/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + even_x * CELL_WIDTH, (even_y + 3.0) * CELL_HEIGHT, 45.0, 45.0],
                 sheet_region: [coord4/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             }); //FOOD
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + (even_x + num_b) * CELL_WIDTH, (even_y + 3.0) * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
                 sheet_region: [coord4/1408.0, 0.0, 17.0/1408.0, 32.0/320.0],
+                ..Default::default()
             });
             print!("FOOD: {:#?}", sprites.len());
         } else { 
@@ -111,35 +128,42 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
             } else {
                 coord1 = 832.0; coord2 = 896.0; coord3 = 0.0; coord4 = 64.0;
             }
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + odd_x * CELL_WIDTH, odd_y * CELL_HEIGHT, CELL_WIDTH, 4.0*CELL_HEIGHT],
                 sheet_region: [1280.0/1408.0, 0.0, 64.0/1408.0, 256.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + odd_x * CELL_WIDTH, odd_y * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region:  [coord1/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + odd_x * CELL_WIDTH, (odd_y + 1.0) * CELL_HEIGHT, 45.0, 45.0],
                 sheet_region: [coord2/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             }); 
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + (odd_x - num_a)* CELL_WIDTH, (odd_y + 1.0) * CELL_HEIGHT,  CELL_WIDTH, CELL_HEIGHT],
                 sheet_region: [coord2/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             });
             print!("FOOD: {:#?}", sprites.len());
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + odd_x * CELL_WIDTH, (odd_y + 2.0) * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region: [coord3/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [4.0 * x as f32 * CELL_WIDTH + odd_x * CELL_WIDTH, (odd_y + 3.0) * CELL_HEIGHT, 45.0, 45.0],
                 sheet_region: [coord4/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                ..Default::default()
             }); 
             if x == 1 {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Items, GPUSprite {
                     screen_region: [4.0 * x as f32 * CELL_WIDTH + (odd_x + num_b) * CELL_WIDTH, (odd_y + 3.0) * CELL_HEIGHT,  CELL_WIDTH, CELL_HEIGHT],
                     sheet_region: [coord4/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+                    ..Default::default()
                 });
             }
             print!("FOOD: {:#?}", sprites.len());
@@ -148,67 +172,81 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
     print!("AISLE-END: {:#?}", sprites.len());
 
     // GROCERY LIST
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.0 * CELL_WIDTH, 1.0 * CELL_HEIGHT, 80.0, 320.0],
         sheet_region: [960.0/1408.0, 64.0/320.0, 64.0/1408.0, 120.0/320.0], 
+        ..Default::default()
     }); // banana -  99
     print!("LIST: {:#?}", sprites.len());
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 1.0 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [64.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     }); //bread - 100
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 1.8 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [192.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     });//carrot - 101
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 2.6 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [320.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     }); //salad - 102
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 3.4 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [1024.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     }); //cereal - 103
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 4.2 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [512.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     });//ketchup - 104
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 5.0 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [704.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     }); //potato chip - 105
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Hud, GPUSprite {
         screen_region: [1.6 * CELL_WIDTH, 5.8 * CELL_HEIGHT, 30.0, 30.0],
         sheet_region: [896.0/1408.0, 0.0, 17.0/1408.0, 32.0/320.0], 
+        ..Default::default()
     });
 
     // CASHIER 
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [18.0 * CELL_WIDTH, 10.0 * CELL_HEIGHT, 64.0, 128.0],
         sheet_region: [576.0/1408.0, 0.0, 64.0/1408.0, 128.0/320.0], 
+        ..Default::default()
     });
 
     print!("ASSOC-START: {:#?}", sprites.len());
     // creating enemy ASSOCIATES (sprites 106-111)
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [4.0 * CELL_WIDTH, 14.0 * CELL_HEIGHT,CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05],
+        ..Default::default()
     });
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [4.0 * CELL_WIDTH, 2.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05], 
+        ..Default::default()
     });
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [12.0 * CELL_WIDTH, 7.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05], 
+        ..Default::default()
     });
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [16.0 * CELL_WIDTH, 2.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05], 
+        ..Default::default()
     });
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [16.0 * CELL_WIDTH, 13.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05], 
+        ..Default::default()
     });
     
     print!("ASSOC-END: {:#?}", sprites.len());
@@ -216,6 +254,42 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
 
 }
 
+// ASSOCIATES are the ones with this sheet column (they're the only sprites that walk)
+pub fn associates(sprites: &SpriteList) -> Vec<usize> {
+    (1..sprites.len()).filter(|&i| sprites[i].sheet_region[0] == 0.54545454545454545454).collect()
+}
+
+// walls and aisles block light in the after-hours store
+pub fn occluders(sprites: &SpriteList) -> OccluderGrid {
+    let mut grid = OccluderGrid::new([0.0, 0.0], [CELL_WIDTH, CELL_HEIGHT], NUMBER_OF_CELLS_W as usize, NUMBER_OF_CELLS_H as usize);
+    for i in 0..sprites.len() {
+        if sprites.layer(i) == Layer::Shelves {
+            grid.fill_rect(sprites[i].screen_region);
+        }
+    }
+    grid
+}
+
+// clips for the player (sprite[0])
+pub fn player_animator() -> Animator {
+    let cart = [384.0/1408.0, 0.0, 64.0/1408.0, 128.0/320.0];
+    let mut anim = Animator::default();
+    anim.add_clip("idle", Clip::new(vec![cart], 0.0, PlayMode::Loop));
+    // blink the cart when an associate catches you (the odd frames are hidden, see
+    // caught_blink), the game ends once it's done
+    anim.add_clip("caught", Clip::new(vec![cart; 7], 0.12, PlayMode::Once));
+    anim.play("idle");
+    anim
+}
+
+// how opaque the player is on the current frame of the caught clip
+pub fn caught_blink(anim: &Animator) -> f32 {
+    match anim.frame() {
+        Some(frame) if frame % 2 == 1 => 0.0,
+        _ => 1.0,
+    }
+}
+
 pub fn move_sprite_input(input: &Input, mut sprite_position: [f32; 2], collided_wall: bool, at_door: bool, aisle_left: bool, aisle_right: bool, aisle_top:bool, aisle_bottom:bool) -> [f32; 2] {
         // Update sprite position based on keyboard input
         if input.is_key_pressed(winit::event::VirtualKeyCode::Up) {
@@ -1,20 +1,31 @@
-use std::{borrow::Cow, mem, path::Path};
+use std::{borrow::Cow, path::Path};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
-use std::time::{Instant, Duration, SystemTime};
+use std::time::{Duration, SystemTime};
 mod sprites;
 use engine;
 use engine::{WINDOW_WIDTH, WINDOW_HEIGHT, NUMBER_OF_CELLS_W, NUMBER_OF_CELLS_H, CELL_WIDTH, CELL_HEIGHT};
+use engine::{AppConfig, Instant};
 use engine::input::Input;
 use engine::gpu::{WGPU, CAMERALAYOUT};
-use engine::sprite::{GPUCamera, GPUSprite, SPRITES, SpriteOption};
+use engine::sprite::{overlay_pipeline, sprite_pipeline, GPUCamera, GPUSprite, GPUOverlay, SpriteList, SpriteBuffer};
+use engine::texture::{TextureOptions, TextureRegistry};
+use engine::background::BackgroundLayer;
+use engine::postfx::{Effect, PostFx};
+use engine::transition::{Transition, TransitionKind};
+use engine::capture::Capture;
+use engine::debug::{self, DebugOverlay};
+use engine::assets::Assets;
+use engine::hotreload::{AssetWatcher, ShaderWatcher};
+use engine::particles::{particle_texture, Emitter, EmitterConfig, ParticleSystem, SQUARE_FRAME};
 use engine::gamestate::GameState; 
-use engine::sound::{winner_sound};
+use engine::sound;
+use engine::tween::{Ease, Tween};
 
-fn score(sprites: Vec<GPUSprite>) -> (i32, i32) {
+fn score(sprites: &[GPUSprite]) -> (i32, i32) {
     let mut bananas : i32 = 0; 
     let mut cabbage : i32 = 0; 
 
@@ -29,14 +40,34 @@ fn score(sprites: Vec<GPUSprite>) -> (i32, i32) {
     (bananas, cabbage)
 }
 
-async fn run(event_loop: EventLoop<()>, window: Window) {
+// post-processing for each game state: a CRT look on the title and a vignette elsewhere
+fn scene_effects(state: usize) -> Vec<Effect> {
+    match state {
+        0 => vec![Effect::Crt { scanlines: 0.3, curvature: 0.06 }],
+        _ => vec![Effect::Vignette { strength: 0.5, radius: 0.6, softness: 0.5 }],
+    }
+}
+
+// how the screen changes between game states: wipe into the match, crossfade to the
+// results, fade back to the title
+fn scene_transition(from: usize, to: usize) -> TransitionKind {
+    match (from, to) {
+        (0, 1) => TransitionKind::Wipe { angle: 0.0, softness: 0.15 },
+        (_, 0) => TransitionKind::Fade { color: [0.0, 0.0, 0.0] },
+        _ => TransitionKind::Crossfade,
+    }
+}
+
+async fn run(event_loop: EventLoop<()>, window: Window, mut app: AppConfig) {
 
     let mut game_state = GameState { state: 0 };
     //engine::sound::sound(); 
 
-    log::info!("Use sprite mode {:?}", SPRITES);
-    
-    let mut gpu = WGPU::new(&window).await;
+    let mut gpu = match WGPU::new(&window, &app).await {
+        Ok(gpu) => gpu,
+        Err(e) => return engine::error::report(&e),
+    };
+    let sprite_option = gpu.sprite_option;
     
     // Load the shaders from disk
     let shader = gpu.device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -49,188 +80,43 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../engine/src/shader2.wgsl"))),
     });
 
-    let texture_bind_group_layout =
-        gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            // It needs the first entry for the texture and the second for the sampler.
-            // This is like defining a type signature.
-            entries: &[
-                // The texture binding
-                wgpu::BindGroupLayoutEntry {
-                    // This matches the binding in the shader
-                    binding: 0,
-                    // Only available in the fragment shader
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // It's a texture binding
-                    ty: wgpu::BindingType::Texture {
-                        // We can use it with float samplers
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        // It's being used as a 2D texture
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        // This is not a multisampled texture
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                // The sampler binding
-                wgpu::BindGroupLayoutEntry {
-                    // This matches the binding in the shader
-                    binding: 1,
-                    // Only available in the fragment shader
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // It's a sampler
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    // No count
-                    count: None,
-                },
-            ],
-        });
-    let sprite_bind_group_layout = match SPRITES {
-        SpriteOption::Storage => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    CAMERALAYOUT,
-                    wgpu::BindGroupLayoutEntry {
-                        // This matches the binding in the shader
-                        binding: 1,
-                        // Available in vertex shader
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        // It's a buffer
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        // No count, not a buffer array binding
-                        count: None,
-                    },
-                ],
-            })
-        }
-        SpriteOption::Uniform => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    CAMERALAYOUT,
-                    wgpu::BindGroupLayoutEntry {
-                        // This matches the binding in the shader
-                        binding: 1,
-                        // Available in vertex shader
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        // It's a buffer
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(SPRITE_UNIFORM_SIZE),
-                        },
-                        // No count, not a buffer array binding
-                        count: None,
-                    },
-                ],
-            })
-        }
-        SpriteOption::VertexBuffer => {
-            gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[CAMERALAYOUT],
-            })
-        }
-    };
+    let mut textures = TextureRegistry::new(&gpu);
+    let mut sprite_buffer = SpriteBuffer::new(&gpu);
     let pipeline_layout = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&sprite_bind_group_layout, &texture_bind_group_layout],
+        bind_group_layouts: &[&sprite_buffer.layout, &textures.layout],
         push_constant_ranges: &[],
     });
 
-    let pipeline_layout_over = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    // full-screen images get an offset uniform so they can slide around
+    let overlay_bind_group_layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&texture_bind_group_layout],
-        push_constant_ranges: &[],
+        entries: &[CAMERALAYOUT],
     });
 
-    let render_pipeline_full = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    let pipeline_layout_over = gpu.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        layout: Some(&pipeline_layout_over),
-        vertex: wgpu::VertexState {
-            module: &shader2,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader2,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
+        bind_group_layouts: &[&textures.layout, &overlay_bind_group_layout],
+        push_constant_ranges: &[],
     });
 
-    let render_pipeline = gpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: match SPRITES {
-                SpriteOption::Storage => "vs_storage_main",
-                SpriteOption::Uniform => "vs_uniform_main",
-                SpriteOption::VertexBuffer => "vs_vbuf_main",
-            },
-            buffers: match SPRITES {
-                SpriteOption::VertexBuffer => &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<GPUSprite>() as u64,
-                    step_mode: wgpu::VertexStepMode::Instance,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            format: wgpu::VertexFormat::Float32x4,
-                            offset: 0,
-                            shader_location: 0,
-                        },
-                        wgpu::VertexAttribute {
-                            format: wgpu::VertexFormat::Float32x4,
-                            offset: std::mem::size_of::<[f32; 4]>() as u64,
-                            shader_location: 1,
-                        },
-                    ],
-                }],
-                _ => &[],
-            },
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(gpu.config.format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
+    let mut render_pipeline_full = overlay_pipeline(&gpu, &pipeline_layout_over, &shader2);
+
+    let mut render_pipeline = sprite_pipeline(&gpu, &pipeline_layout, &shader, sprite_option);
+    // in development builds, saving either shader rebuilds its pipeline
+    let mut shaders = ShaderWatcher::new(app.hot_reload);
+    let sprite_shader = shaders.watch("engine/src/shader.wgsl");
+    let overlay_shader = shaders.watch("engine/src/shader2.wgsl");
+    // and saving anything under content/ reloads it
+    let mut content = AssetWatcher::new("content", app.hot_reload);
+    let mut asset_error: Option<String> = None;
 
     gpu.surface.configure(&gpu.device, &gpu.config);
+    // everything from content/ loads in the background behind a loading bar
+    let mut assets = Assets::new();
     let path_sprites = Path::new("content/sprites-overlay.png");
-    let (sprite_tex, _sprite_img) = gpu.load_texture(path_sprites, None)
-        .await
-        .expect("Couldn't load spritesheet texture");
-    let view_sprite = sprite_tex.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_sprite = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-    let texture_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_sprite),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_sprite),
-            },
-        ],
-    });
+    // first in the registry, so it's the texture sprites get by default
+    assets.load_texture(&gpu, &mut textures, path_sprites, TextureOptions::pixel_art());
     let camera = GPUCamera {
         screen_pos: [0.0, 0.0],
         screen_size: [1024.0, 768.0],
@@ -242,7 +128,23 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         mapped_at_creation: false,
     });
 
-    let mut sprites: Vec<GPUSprite> = sprites::create_sprites();
+    let mut overlay = GPUOverlay::default();
+    let buffer_overlay = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: bytemuck::bytes_of(&overlay).len() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let overlay_bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &overlay_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer_overlay.as_entire_binding(),
+        }],
+    });
+
+    let mut sprites: SpriteList = sprites::create_sprites();
     let assoc1 = sprites.len() - 1; 
     let assoc2 = sprites.len() - 2; 
 
@@ -251,100 +153,28 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 
     let mut sprite_position_2: [f32; 2] = [10.0 * CELL_WIDTH, 7.0 * CELL_HEIGHT];  
 
-    const SPRITE_UNIFORM_SIZE: u64 = 512 * mem::size_of::<GPUSprite>() as u64;
-    let buffer_sprite = gpu.device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: if SPRITES == SpriteOption::Uniform {
-            SPRITE_UNIFORM_SIZE
-        } else {
-            sprites.len() as u64 * std::mem::size_of::<GPUSprite>() as u64
-        },
-        usage: match SPRITES {
-            SpriteOption::Storage => wgpu::BufferUsages::STORAGE,
-            SpriteOption::Uniform => wgpu::BufferUsages::UNIFORM,
-            SpriteOption::VertexBuffer => wgpu::BufferUsages::VERTEX,
-        } | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-    let sprite_bind_group = match SPRITES {
-        SpriteOption::VertexBuffer => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &sprite_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer_camera.as_entire_binding(),
-            }],
-        }),
-        _ => gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &sprite_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer_camera.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: buffer_sprite.as_entire_binding(),
-                },
-            ],
-        }),
-    };
 
     // create background stuff
     let path_bgnd = Path::new("content/space.jpeg");
-    let (tex_bgnd, _over_image) = gpu.load_texture(path_bgnd,None)
-        .await
-        .expect("Couldn't load space img");
+    // mirrored so the drifting starfield has no seams
+    let bgnd_options = TextureOptions {
+        address_mode: wgpu::AddressMode::MirrorRepeat,
+        ..TextureOptions::default()
+    };
+    let tex_bgnd = assets.load_texture(&gpu, &mut textures, path_bgnd, bgnd_options);
+    let mut background = BackgroundLayer::new(&gpu, &overlay_bind_group_layout, tex_bgnd, [WINDOW_WIDTH, WINDOW_HEIGHT]);
+    background.parallax = [0.5, 0.5];
+    background.scroll = [0.01, 0.0];
 
-    let view_bgnd = tex_bgnd.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_bgnd = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-    
     // create title
     let path_title = Path::new("content/screen-page-2.png");
-    let (tex_title, _over_image) = gpu.load_texture(path_title,None)
-        .await
-        .expect("Couldn't load space img");
-
-    let view_title = tex_title.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler_title = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-        
-    // set first background to instructions
-    let mut texture_bind_group_bgnd2 = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_title),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_title),
-            },
-        ],
-    });
+    let tex_title = assets.load_texture(&gpu, &mut textures, path_title, TextureOptions::default());
 
-    let mut texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &texture_bind_group_layout,
-        entries: &[
-            // One for the texture, one for the sampler
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view_bgnd),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler_bgnd),
-            },
-        ],
-    });
 
-    gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-    gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
+    gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&gpu.viewport.crop_camera(camera)));
+    sprite_buffer.upload(&gpu, &buffer_camera, &sprites);
     let mut input = Input::default();
+    input.set_viewport(gpu.viewport);
     let mut player1won = false; 
     let mut player2won = false; 
     let mut tie = false;
@@ -352,23 +182,51 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     let mut collided_wall = false;
 
     let path_win = Path::new("content/player2won.png");
-    let (tex_win, _win_image) = gpu.load_texture(path_win,None)
-        .await
-        .expect("Couldn't load game over img");
+    let tex_win = assets.load_texture(&gpu, &mut textures, path_win, TextureOptions::default());
     
     let path_over = Path::new("content/player1won.png");
-    let (tex_over, _over_image) = gpu.load_texture(path_over,None)
-        .await
-        .expect("Couldn't load game over img");
+    let tex_over = assets.load_texture(&gpu, &mut textures, path_over, TextureOptions::default());
     
     let path_tie = Path::new("content/tie.png");
-    let (tex_tie, _over_image) = gpu.load_texture(path_tie,None)
-        .await
-        .expect("Couldn't load game over img");
+    let tex_tie = assets.load_texture(&gpu, &mut textures, path_tie, TextureOptions::default());
+    let winner = assets.load_sound(Path::new("content/winneris.ogg"));
+
+    // confetti raining down over the winner's screen
+    let mut particles = ParticleSystem::default();
+    let confetti = particles.add(Emitter::new(EmitterConfig {
+        rate: 90.0,
+        max_particles: 400,
+        lifetime: [3.0, 4.0],
+        spawn_area: [WINDOW_WIDTH / 2.0, 0.0],
+        angle: [-std::f32::consts::FRAC_PI_2 - 0.5, -std::f32::consts::FRAC_PI_2 + 0.5],
+        speed: [60.0, 160.0],
+        gravity: [0.0, -60.0],
+        spin: [-6.0, 6.0],
+        size: [10.0, 6.0],
+        colors: vec![
+            [1.0, 0.3, 0.3, 1.0],
+            [1.0, 0.9, 0.2, 1.0],
+            [0.3, 0.9, 0.4, 1.0],
+            [0.3, 0.6, 1.0, 1.0],
+            [0.9, 0.4, 1.0, 1.0],
+        ],
+        frames: vec![SQUARE_FRAME],
+        texture: particle_texture(&gpu, &mut textures),
+        ..EmitterConfig::default()
+    }));
+    particles.emitter(confetti).position = [WINDOW_WIDTH / 2.0, WINDOW_HEIGHT + 10.0];
 
     // begins timer
     let mut start = Instant::now();
     let mut count: i32 = 0; 
+    let mut prev_frame = Instant::now();
+    let mut shown_state = game_state.state;
+    let mut postfx = PostFx::new(&gpu, &textures);
+    postfx.effects = scene_effects(shown_state);
+    let mut transition = Transition::new(&gpu);
+    let mut capture = Capture::default();
+    let mut debug_overlay = DebugOverlay::new(&gpu, &mut textures);
+    let mut end_slide = Tween::new(0.0, 0.0, 0.0, Ease::Linear);
 
     event_loop.run(move |event, _, control_flow| {
 
@@ -379,9 +237,24 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 ..
             } => {
                 gpu.resize(size);
+                input.set_viewport(gpu.viewport);
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
+                let dt = prev_frame.elapsed().as_secs_f32();
+                prev_frame = Instant::now();
+                let update_start = Instant::now();
+                #[cfg(target_arch = "wasm32")]
+                engine::web::fit_canvas(&window);
+                debug_overlay.frame(dt);
+                let failed = assets.errors().len();
+                assets.update(&gpu, &mut textures);
+                debug_overlay.set_loading((!assets.is_done()).then(|| assets.progress()));
+                if let Some(error) = assets.errors()[failed..].last() {
+                    asset_error = Some(error.to_string());
+                    debug_overlay.set_error(shaders.error().or(asset_error.as_deref()));
+                }
+
                 if player1won {
                     game_state.state = 2;
                     if input.is_key_pressed(winit::event::VirtualKeyCode::Space) {
@@ -473,7 +346,8 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         start = Instant::now();
                     }
 
-                    if input.is_key_pressed(winit::event::VirtualKeyCode::Space) {
+                    // nothing starts until the screens have loaded
+                    if assets.is_done() && input.is_key_pressed(winit::event::VirtualKeyCode::Space) {
                         game_state.state = 1
                     }
 
@@ -490,7 +364,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     sprites[assoc2].screen_region[1] = sprite_position_2[1]; 
 
                     if game_state.state == 1  && start.elapsed().as_secs() > 15 {
-                        let (bananas, cabbage) = score(sprites.clone()); 
+                        let (bananas, cabbage) = score(&sprites); 
 
                         if bananas > cabbage {
                             player1won = true;
@@ -504,15 +378,105 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     }
                 }
                 
+                app.handle_hotkeys(&input, &window);
+                capture.handle_hotkeys(&input);
+                debug_overlay.handle_hotkeys(&input);
+
                 // Then send the data to the GPU!
                 input.next_frame();
 
-                gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&camera));
-                gpu.queue.write_buffer(&buffer_sprite, 0, bytemuck::cast_slice(&sprites));
+                // slide the end screens in from the top when they first show up
+                if game_state.state != shown_state {
+                    transition.start_with(scene_transition(shown_state, game_state.state), 0.6);
+                    shown_state = game_state.state;
+                    postfx.effects = scene_effects(shown_state);
+                    if shown_state >= 2 {
+                        end_slide = Tween::new(2.0, 0.0, 0.8, Ease::BounceOut);
+                    }
+                    let celebrating = shown_state == 2 || shown_state == 3;
+                    if celebrating {
+                        postfx.flash([1.0, 1.0, 1.0], 0.5);
+                    }
+                    particles.emitter(confetti).emitting = celebrating;
+                    if !celebrating {
+                        particles.clear();
+                    }
+                }
+                particles.update(dt);
+                let slide = if game_state.state >= 2 { end_slide.update(dt) } else { 0.0 };
+                let (overlay_scale, overlay_offset) = gpu.viewport.overlay_transform();
+                overlay.scale = overlay_scale;
+                overlay.offset = [overlay_offset[0], overlay_offset[1] + slide * overlay_scale[1]];
+
+                gpu.queue.write_buffer(&buffer_camera, 0, bytemuck::bytes_of(&gpu.viewport.crop_camera(camera)));
+                background.update(&gpu, dt, gpu.viewport.crop_camera(camera));
+                gpu.queue.write_buffer(&buffer_overlay, 0, bytemuck::bytes_of(&overlay));
+                // the end screens cover the board, so only the confetti goes up with them
+                if game_state.state >= 2 {
+                    let mut confetti_sprites = SpriteList::new();
+                    particles.push_sprites(&mut confetti_sprites);
+                    sprite_buffer.upload(&gpu, &buffer_camera, &confetti_sprites);
+                } else {
+                    sprite_buffer.upload(&gpu, &buffer_camera, &sprites);
+                }
 
-                let frame = gpu.surface
-                    .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
+                // collision boxes, when the overlay is up
+                if debug_overlay.visible && game_state.state == 1 {
+                    debug_overlay.grid([0.0, 0.0], [CELL_WIDTH, CELL_HEIGHT], NUMBER_OF_CELLS_W as usize, NUMBER_OF_CELLS_H as usize, debug::GREY);
+                    for wall in &sprites[..70] {
+                        debug_overlay.rect(wall.screen_region, debug::RED);
+                    }
+                    debug_overlay.rect(sprites[assoc1].screen_region, debug::GREEN);
+                    debug_overlay.rect(sprites[assoc2].screen_region, debug::YELLOW);
+                }
+                let shaders_changed = shaders.poll(dt);
+                if shaders_changed {
+                    if let Some(pipeline) = shaders.reload(&gpu, sprite_shader, |m| sprite_pipeline(&gpu, &pipeline_layout, m, sprite_option)) {
+                        render_pipeline = pipeline;
+                    }
+                    if let Some(pipeline) = shaders.reload(&gpu, overlay_shader, |m| overlay_pipeline(&gpu, &pipeline_layout_over, m)) {
+                        render_pipeline_full = pipeline;
+                    }
+                }
+                // images are updated in place; sounds, text and atlases load again in the
+                // background and are swapped in by assets.update
+                for path in assets.take_reloaded() {
+                    log::info!("Reloaded {}", path.display());
+                }
+                let changed_assets = content.poll(dt);
+                if !changed_assets.is_empty() {
+                    asset_error = None;
+                }
+                for path in &changed_assets {
+                    assets.reload(path);
+                    if let Err(e) = textures.reload(&gpu, path) {
+                        log::warn!("{}", e);
+                        asset_error = Some(e.to_string());
+                    }
+                }
+                if shaders_changed || !changed_assets.is_empty() {
+                    debug_overlay.set_error(shaders.error().or(asset_error.as_deref()));
+                }
+                debug_overlay.set_update_time(update_start.elapsed());
+                debug_overlay.upload(&gpu, &buffer_camera, &sprite_buffer);
+                let render_start = Instant::now();
+
+                postfx.update(&gpu, dt);
+                transition.update(&gpu, dt);
+                capture.update(dt);
+
+                let frame = match gpu.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => {
+                        window.request_redraw();
+                        return;
+                    }
+                    Err(e) => {
+                        engine::error::report(&e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                };
                 let view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
@@ -523,7 +487,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: None,
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: postfx.scene_view(transition.scene_view(&view)),
                             resolve_target: None,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
@@ -532,134 +496,66 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         })],
                         depth_stencil_attachment: None,
                     });
+                    // letterbox the game screen into the window
+                    gpu.viewport.apply(&mut rpass);
 
                     match game_state.state {
                         0 => {
                             // draw instructions
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd2, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_title), &[]);
                             rpass.draw(0..6, 0..1);
                         }
                         2 => {                        
-                            let tex_end = &tex_over;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
-    
                             // Draw end game screen
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_over), &[]);
                             rpass.draw(0..6, 0..1);
+                            sprite_buffer.draw(&mut rpass, &render_pipeline, &textures);
                         }
                         3 => {
-                            let tex_end = &tex_win;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
-    
                             // Draw end game screen
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_win), &[]);
                             rpass.draw(0..6, 0..1);
+                            sprite_buffer.draw(&mut rpass, &render_pipeline, &textures);
                         }
                         4 => {
-                            let tex_end = &tex_tie;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
                             // Draw end game screen
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
+                            rpass.set_bind_group(1, &overlay_bind_group, &[]);
+                            rpass.set_bind_group(0, textures.bind_group(tex_tie), &[]);
                             rpass.draw(0..6, 0..1);
                         }
                         _ => {
                             // Draw space background
-                            let tex_end = &tex_bgnd;
-                            let view_end = tex_end.create_view(&wgpu::TextureViewDescriptor::default());
-                            let sampler_end = gpu.device.create_sampler(&wgpu::SamplerDescriptor::default());
-                                
-                            texture_bind_group_bgnd = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: None,
-                                layout: &texture_bind_group_layout,
-                                entries: &[
-                                    // One for the texture, one for the sampler
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&view_end),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::Sampler(&sampler_end),
-                                    },
-                                ],
-                            });
-
                             rpass.set_pipeline(&render_pipeline_full);
-                            rpass.set_bind_group(0, &texture_bind_group_bgnd, &[]);
-                            rpass.draw(0..6, 0..1);
+                            background.draw(&mut rpass, &textures);
                             {
-                                rpass.set_pipeline(&render_pipeline);
-                                if SPRITES == SpriteOption::VertexBuffer {
-                                    rpass.set_vertex_buffer(0, buffer_sprite.slice(..));
-                                }
-                                rpass.set_bind_group(0, &sprite_bind_group, &[]);
-                                rpass.set_bind_group(1, &texture_bind_group, &[]);
-                                rpass.draw(0..6, 0..(sprites.len() as u32));
+                                sprite_buffer.draw(&mut rpass, &render_pipeline, &textures);
                             }
                         }
                     }
+                    debug_overlay.draw(&mut rpass, &render_pipeline, &textures);
                 }
                 if count == 0 && (game_state.state == 2 || game_state.state == 3){
-                    winner_sound();
+                    if let Some(Err(e)) = assets.sound(winner).map(sound::play) {
+                        log::warn!("{}", e);
+                    }
                     count += 1; 
                 }
+                postfx.apply(&gpu, &mut encoder, &textures, transition.scene_view(&view));
+                transition.apply(&gpu, &mut encoder, &view);
+                if let Some(scene) = transition.scene_texture() {
+                    capture.capture(&gpu, &mut encoder, scene);
+                }
                 gpu.queue.submit(Some(encoder.finish()));
+                capture.finish(&gpu);
                 frame.present();
+                debug_overlay.set_render_time(render_start.elapsed());
                 window.request_redraw();
             }
             Event::WindowEvent {
@@ -672,12 +568,16 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 event: WindowEvent::KeyboardInput { input: key_ev, .. },
                 ..
             } => {
+                // the browser only lets audio start once the player has done something
+                // (no audio is reported when a sound plays)
+                let _ = sound::unlock();
                 input.handle_key_event(key_ev);
             }
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { state, button, .. },
                 ..
             } => {
+                let _ = sound::unlock();
                 input.handle_mouse_button(state, button);
             }
             Event::WindowEvent {
@@ -692,27 +592,38 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 }
 
 fn main() {
-    let event_loop = EventLoop::new();
-    let window = winit::window::Window::new(&event_loop).unwrap();
+    #[cfg(feature = "embed")]
+    engine::vfs::mount(engine::vfs::Source::Embedded(engine::embed_files!("../content" as "content":
+        "sprites-overlay.png",
+        "space.jpeg",
+        "screen-page-2.png",
+        "player2won.png",
+        "player1won.png",
+        "tie.png",
+        "winneris.ogg",
+    )));
+    // logging first, so anything below that fails gets reported
     #[cfg(not(target_arch = "wasm32"))]
+    env_logger::init();
+    #[cfg(target_arch = "wasm32")]
     {
-        env_logger::init();
-        pollster::block_on(run(event_loop, window));
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        let _ = console_log::init_with_level(log::Level::Info);
     }
+    let event_loop = EventLoop::new();
+    let app = AppConfig {
+        title: "BANANAS vs. CABBAGE".to_string(),
+        ..AppConfig::default()
+    };
+    let window = match app.build_window(&event_loop) {
+        Ok(window) => window,
+        Err(e) => return engine::error::report(&e),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(run(event_loop, window, app));
     #[cfg(target_arch = "wasm32")]
     {
-        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-        console_log::init_with_level(log::Level::Trace).expect("could not initialize logger");
-        use winit::platform::web::WindowExtWebSys;
-        // On wasm, append the canvas to the document body
-        web_sys::window()
-            .and_then(|win| win.document())
-            .and_then(|doc| doc.body())
-            .and_then(|body| {
-                body.append_child(&web_sys::Element::from(window.canvas()))
-                    .ok()
-            })
-            .expect("couldn't append canvas to document body");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window));
+        engine::web::attach_canvas(&window);
+        wasm_bindgen_futures::spawn_local(run(event_loop, window, app));
     }
 }
\ No newline at end of file
@@ -1,56 +1,62 @@
 use rand::Rng;
 use engine::{WINDOW_WIDTH, WINDOW_HEIGHT, NUMBER_OF_CELLS_W, NUMBER_OF_CELLS_H, CELL_WIDTH, CELL_HEIGHT};
 use engine::input::Input;
-use engine::sprite::GPUSprite;
+use engine::sprite::{GPUSprite, Layer, SpriteList};
 
 //value to not hard code the door placement
 pub const door_xvalue: i32 = NUMBER_OF_CELLS_W/3; 
 
-pub fn create_sprites() ->  Vec<GPUSprite> {
+pub fn create_sprites() ->  SpriteList {
     // CUSTOMER (AKA PLAYER)
-    let mut sprites: Vec<GPUSprite> = vec![];
+    let mut sprites = SpriteList::new();
 
     //WALLS: sprite[0] to sprite[69]!! 
     for y in 0..NUMBER_OF_CELLS_H {
         let y_value = y as f32 * CELL_HEIGHT;
         if y == 0 {
             for x in 0..NUMBER_OF_CELLS_W {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region: [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0], 
+                    ..Default::default()
                 });
             }
         }
         // top of the wall & door
         else if y == (NUMBER_OF_CELLS_H - 1) {
             for x in 0..door_xvalue {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region: [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0], 
+                    ..Default::default()
                 });
             } 
             for x in door_xvalue..door_xvalue + 1 {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region:  [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0],
+                    ..Default::default()
                 });
             } for x in door_xvalue+1..NUMBER_OF_CELLS_W {
-                sprites.push(GPUSprite {
+                sprites.push(Layer::Shelves, GPUSprite {
                     screen_region: [x as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                     sheet_region:  [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0], 
+                    ..Default::default()
                 });
             }
         }
         // sides of the wall 
         else {
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [0 as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                 sheet_region:  [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0], 
+                ..Default::default()
             });
             //right side of the wall 
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Shelves, GPUSprite {
                 screen_region: [(NUMBER_OF_CELLS_W - 1) as f32 * CELL_WIDTH, y_value, 50.0, 50.0],
                 sheet_region:  [1088.0/1408.0, 0.0, 1.0/1408.0, 1.0/320.0], 
+                ..Default::default()
             });
         }
     }
@@ -58,9 +64,10 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
     //BANANAS: 70-202
     for x in 1..NUMBER_OF_CELLS_W-1 {
         for y in 1..8 {
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [x as f32 * CELL_WIDTH, y as f32 * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region: [0.0, 0.0, 64.0/1408.0, 0.2], 
+                ..Default::default()
             });
         }
     }
@@ -68,22 +75,25 @@ pub fn create_sprites() ->  Vec<GPUSprite> {
     //CABBAGE: 203-335
     for x in 1..NUMBER_OF_CELLS_W-1 {
         for y in 8..NUMBER_OF_CELLS_H-1 {
-            sprites.push(GPUSprite {
+            sprites.push(Layer::Items, GPUSprite {
                 screen_region: [x as f32 * CELL_WIDTH, y as f32 * CELL_HEIGHT, 50.0, 50.0],
                 sheet_region: [960.0/1408.0, 0.0, 64.0/1408.0, 64.0/320.0], 
+                ..Default::default()
             });
         }
     }
 
     //ASSOCIATE PLAYER - FOR GAME 2 (green)
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [9.0 * CELL_WIDTH, 8.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.05, 0.01136364, 0.05],
+        ..Default::default()
     });
     //ASSOCIATE PLAYER - FOR GAME 2 (red)
-    sprites.push(GPUSprite {
+    sprites.push(Layer::Characters, GPUSprite {
         screen_region: [10.0 * CELL_WIDTH, 7.0 * CELL_HEIGHT, CELL_WIDTH, CELL_HEIGHT],
         sheet_region: [0.54545454545454545454, 0.0, 0.01136364, 0.05],
+        ..Default::default()
     });
 
     sprites
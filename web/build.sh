@@ -0,0 +1,18 @@
+#!/bin/sh
+# Build both games for the browser into web/pkg and copy content/ next to them.
+# Needs the wasm32-unknown-unknown target and wasm-bindgen-cli (the same version as the
+# wasm-bindgen crate in Cargo.lock). Serve with e.g. `python3 -m http.server -d web` and
+# open http://localhost:8000/?game=game1
+set -e
+cd "$(dirname "$0")/.."
+profile=${PROFILE:-release}
+# cargo puts the dev profile in target/.../debug
+dir=$profile
+[ "$profile" = dev ] && dir=debug
+cargo build --profile "$profile" --target wasm32-unknown-unknown --bin game1 --bin game2
+for game in game1 game2; do
+    wasm-bindgen --target web --no-typescript --out-dir "web/pkg/$game" \
+        "target/wasm32-unknown-unknown/$dir/$game.wasm"
+done
+rm -rf web/content
+cp -r content web/content